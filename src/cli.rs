@@ -1,9 +1,16 @@
+use crate::iface;
+use crate::rtp;
 use clap::{Arg, ArgMatches, Command};
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 pub struct Group {
-    pub addr: Ipv4Addr,
+    pub addr: IpAddr,
+    /// address of the interface on which an IPv4 group is joined (unspecified to let the OS choose)
     pub ifaddr: Ipv4Addr,
+    /// index of the interface on which an IPv6 group is joined (0 to let the OS choose)
+    pub ifindex: u32,
+    /// senders to which a source-specific join is restricted, or empty for an any-source join
+    pub sources: Vec<IpAddr>,
 }
 
 pub enum Fec {
@@ -16,10 +23,171 @@ pub struct NetCmd {
     pub group: Option<Group>,
     pub fec: Fec,
     pub udpts: bool,
+    /// maximum number of datagrams to receive per system call
+    pub batch: usize,
+    /// socket receive buffer size to ask for, in bytes
+    pub rcvbuf: Option<usize>,
+    /// number of RTP packets that may be held back waiting for a missing one
+    pub reorder_window: usize,
+    /// only RTP packets with this SSRC are processed, if given
+    pub ssrc: Option<u32>,
+    /// only datagrams from this sender are processed, if given
+    pub sender: Option<Sender>,
+    /// the second network path of a SMPTE ST 2022-7 protected stream, if given
+    pub path_b: Option<Path>,
+    pub format: OutputFormat,
+    pub timescale: u64,
+}
+
+/// Where the second copy of a stream protected by SMPTE ST 2022-7 is received
+pub struct Path {
+    pub addr: SocketAddr,
+    pub group: Option<Group>,
+}
+
+/// The address of a sender, optionally including the port it sends from
+#[derive(Clone, Copy)]
+pub struct Sender {
+    pub addr: IpAddr,
+    pub port: Option<u16>,
+}
+impl Sender {
+    /// The same sender, whichever port it sends from
+    pub fn any_port(self) -> Sender {
+        Sender { port: None, ..self }
+    }
+}
+impl std::str::FromStr for Sender {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(Sender {
+                addr: addr.ip(),
+                port: Some(addr.port()),
+            });
+        }
+        let addr = s.parse().map_err(|_| "invalid --sender address")?;
+        Ok(Sender { addr, port: None })
+    }
+}
+
+/// A point within a file, given either directly as a byte offset or as a time which must be found
+/// by examining PCR values
+pub enum Position {
+    /// byte offset from the start of the file
+    Offset(u64),
+    /// seconds since the first PCR in the file
+    Seconds(f64),
+    /// absolute 90kHz timestamp value
+    Pts(u64),
+}
+impl std::str::FromStr for Position {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(pts) = s.strip_prefix("pts:") {
+            pts.parse()
+                .map(Position::Pts)
+                .map_err(|_| "invalid pts value")
+        } else if let Some(secs) = s.strip_suffix('s') {
+            match secs.parse::<f64>() {
+                Ok(secs) if secs >= 0.0 => Ok(Position::Seconds(secs)),
+                _ => Err("invalid seconds value"),
+            }
+        } else {
+            s.parse()
+                .map(Position::Offset)
+                .map_err(|_| "invalid byte offset")
+        }
+    }
+}
+
+/// How each cue found is to be presented
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// a dump of the decoded section
+    Dump,
+    /// HLS `EXT-X-DATERANGE` tags
+    HlsDaterange,
+    /// HLS `EXT-X-CUE-OUT` / `EXT-X-CUE-IN` and `EXT-OATCLS-SCTE35` tags
+    HlsCue,
+    /// DASH MPD `EventStream` with SCTE 35 XML markup
+    DashXml,
+    /// DASH MPD `EventStream` with the binary section in base64
+    DashXmlBin,
+    /// a SCTE 35 XML `SpliceInfoSection` element
+    Scte35Xml,
+}
+impl OutputFormat {
+    /// Formats diagnostic text to be placed among the output for cues, as a comment in the
+    /// format's syntax
+    pub fn comment(self, text: &str) -> String {
+        match self {
+            OutputFormat::Dump => text.to_string(),
+            OutputFormat::HlsDaterange | OutputFormat::HlsCue => format!("# {}", text),
+            OutputFormat::DashXml | OutputFormat::DashXmlBin | OutputFormat::Scte35Xml => {
+                format!("<!-- {} -->", text)
+            }
+        }
+    }
+}
+impl std::str::FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dump" => Ok(OutputFormat::Dump),
+            "hls-daterange" => Ok(OutputFormat::HlsDaterange),
+            "hls-cue" => Ok(OutputFormat::HlsCue),
+            "dash-xml" => Ok(OutputFormat::DashXml),
+            "dash-xml-bin" => Ok(OutputFormat::DashXmlBin),
+            "scte35-xml" => Ok(OutputFormat::Scte35Xml),
+            _ => Err("invalid --format value"),
+        }
+    }
+}
+
+fn format_args() -> [Arg; 2] {
+    [
+        Arg::new("format")
+            .long("format")
+            .num_args(1)
+            .value_names(["dump|hls-daterange|hls-cue|dash-xml|dash-xml-bin|scte35-xml"])
+            .help("How to present each cue: as a dump of the decoded section (the default), as the HLS playlist tags or DASH EventStream that it should produce, or as SCTE 35 XML"),
+        Arg::new("timescale")
+            .long("timescale")
+            .num_args(1)
+            .help("Timescale of DASH EventStream output (defaults to 90000)"),
+    ]
+}
+
+fn output_format(matches: &ArgMatches) -> Result<(OutputFormat, u64), &'static str> {
+    let format = matches
+        .get_one::<String>("format")
+        .map(|s| s.parse())
+        .transpose()?
+        .unwrap_or(OutputFormat::Dump);
+    let timescale = match matches.get_one::<String>("timescale") {
+        Some(s) => match s.parse() {
+            Ok(t) if t > 0 => t,
+            _ => return Err("invalid --timescale value"),
+        },
+        None => 90_000,
+    };
+    Ok((format, timescale))
 }
 
 pub struct FileCmd {
-    pub name: String,
+    pub names: Vec<String>,
+    pub jobs: usize,
+    pub scan: bool,
+    pub start: Option<Position>,
+    pub end: Option<Position>,
+    pub max_cues: Option<u64>,
+    pub follow: bool,
+    pub format: OutputFormat,
+    pub timescale: u64,
 }
 
 pub enum SectEncoding {
@@ -30,37 +198,305 @@ pub enum SectEncoding {
 pub struct SectCmd {
     pub value: String,
     pub encoding: SectEncoding,
+    pub format: OutputFormat,
+    pub timescale: u64,
+}
+
+pub struct HlsCmd {
+    pub name: String,
+}
+
+pub struct XmlCmd {
+    pub name: String,
+}
+
+pub struct MonitorCmd {
+    pub config: String,
+}
+
+pub struct Mp4Cmd {
+    pub names: Vec<String>,
 }
 
 pub enum CommandSpec {
     Net(NetCmd),
     File(FileCmd),
+    Hls(HlsCmd),
+    Xml(XmlCmd),
+    Mp4(Mp4Cmd),
     Section(SectCmd),
+    ListInterfaces,
+    Monitor(MonitorCmd),
 }
 
-fn group(matches: &ArgMatches) -> Option<Group> {
-    matches.get_one::<String>("mcast").map(|mcast| {
-        let ifaddr = if let Some(addr) = matches.get_one::<String>("ifaddr") {
-            addr.parse().unwrap()
-        } else {
-            "0.0.0.0".parse().unwrap()
+/// Number of datagrams received per `recvmmsg()` call unless `--batch` says otherwise; enough to
+/// cover around 3ms of an 80Mbps stream
+const BATCH_DEFAULT: usize = 32;
+/// Upper limit on `--batch`, which sizes buffers of 9000 bytes per datagram
+const BATCH_MAX: usize = 1024;
+
+/// Options describing a network input, given on the command line of the `net` subcommand, or for
+/// each input in the configuration of the `monitor` subcommand
+#[derive(Default)]
+pub struct NetOptions<'a> {
+    pub port: u16,
+    pub bind: Option<&'a str>,
+    pub group: Option<&'a str>,
+    pub ifaddr: Option<&'a str>,
+    pub ifindex: Option<&'a str>,
+    pub iface: Option<&'a str>,
+    pub sources: Vec<&'a str>,
+    pub udp: bool,
+    pub fec: Option<&'a str>,
+    pub batch: Option<&'a str>,
+    pub rcvbuf: Option<usize>,
+    pub reorder_window: Option<usize>,
+    pub ssrc: Option<u32>,
+    pub sender: Option<&'a str>,
+    /// the second network path of a SMPTE ST 2022-7 protected stream, described by the `port`,
+    /// `bind`, `group`, `ifaddr`, `ifindex`, `iface` and `sources` of these options
+    pub path_b: Option<Box<NetOptions<'a>>>,
+}
+/// Parses an SSRC given in decimal, or in hex with a `0x` prefix as SSRCs are printed
+pub fn parse_ssrc(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| "invalid --ssrc value".to_string())
+}
+
+impl NetOptions<'_> {
+    fn from_matches(matches: &ArgMatches) -> Result<NetOptions<'_>, String> {
+        let arg = |id| matches.get_one::<String>(id).map(String::as_str);
+        Ok(NetOptions {
+            port: arg("port").unwrap().parse().map_err(|_| "invalid port")?,
+            bind: arg("bind"),
+            group: arg("mcast"),
+            ifaddr: arg("ifaddr"),
+            ifindex: arg("ifindex"),
+            iface: arg("iface"),
+            sources: matches
+                .get_many::<String>("source")
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect(),
+            udp: matches.get_flag("udp"),
+            fec: arg("fec"),
+            batch: arg("batch"),
+            rcvbuf: match arg("rcvbuf") {
+                Some(size) => match size.parse() {
+                    Ok(0) | Err(_) => return Err("invalid --rcvbuf value".into()),
+                    Ok(size) => Some(size),
+                },
+                None => None,
+            },
+            reorder_window: arg("reorder-window")
+                .map(|w| w.parse().map_err(|_| "invalid --reorder-window value"))
+                .transpose()?,
+            ssrc: arg("ssrc").map(parse_ssrc).transpose()?,
+            sender: arg("sender"),
+            path_b: if ["mcast-b", "port-b", "bind-b"]
+                .iter()
+                .any(|id| matches.contains_id(id))
+            {
+                Some(Box::new(NetOptions {
+                    port: match arg("port-b") {
+                        Some(p) => p.parse().map_err(|_| "invalid --port-b")?,
+                        None => arg("port").unwrap().parse().map_err(|_| "invalid port")?,
+                    },
+                    bind: arg("bind-b"),
+                    group: arg("mcast-b"),
+                    ifaddr: arg("ifaddr-b"),
+                    ifindex: arg("ifindex-b"),
+                    iface: arg("iface-b"),
+                    sources: matches
+                        .get_many::<String>("source-b")
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str)
+                        .collect(),
+                    ..NetOptions::default()
+                }))
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Checks the options and resolves addresses and interfaces
+    pub fn net_cmd(&self, format: OutputFormat, timescale: u64) -> Result<NetCmd, String> {
+        let path_b = match self.path_b {
+            Some(ref b) => {
+                if self.udp || self.fec.is_some() || self.sender.is_some() {
+                    return Err(
+                        "--udp, --fec and --sender cannot be used with a second path (--mcast-b, --port-b or --bind-b)"
+                            .into(),
+                    );
+                }
+                Some(b.path(true).map_err(|e| format!("path B: {}", e))?)
+            }
+            None => None,
         };
-        Group {
-            addr: mcast.parse().unwrap(),
-            ifaddr,
+        let path = self.path(path_b.is_some())?;
+        if let Some(ref b) = path_b {
+            let same_group =
+                path.group.as_ref().map(|g| g.addr) == b.group.as_ref().map(|g| g.addr);
+            if b.addr == path.addr && same_group {
+                return Err(
+                    "the second path must have a different address, port or group from the first"
+                        .into(),
+                );
+            }
         }
-    })
-}
+        let fec = match self.fec {
+            Some("prompeg") => Fec::ProMpeg,
+            Some(other) => return Err(format!("unsupported FEC mode {:?}", other)),
+            None => Fec::None,
+        };
+        let batch = match self.batch {
+            Some(b) => match b.parse() {
+                Ok(0) | Err(_) => return Err("invalid --batch value".into()),
+                Ok(b) if b > 1 && !cfg!(target_os = "linux") => {
+                    return Err("--batch greater than 1 is only supported on Linux".into())
+                }
+                Ok(b) if b > BATCH_MAX => {
+                    return Err(format!("--batch may be at most {}", BATCH_MAX))
+                }
+                Ok(b) => b,
+            },
+            None if cfg!(target_os = "linux") => BATCH_DEFAULT,
+            None => 1,
+        };
+        if self.reorder_window.is_some_and(|w| w > rtp::MAX_WINDOW) {
+            return Err(format!(
+                "--reorder-window may be at most {}",
+                rtp::MAX_WINDOW
+            ));
+        }
+        if self.ssrc.is_some() && self.udp {
+            return Err("--ssrc only applies to RTP, not with --udp".into());
+        }
+        let sender = self.sender.map(str::parse).transpose()?;
+        Ok(NetCmd {
+            addr: path.addr,
+            group: path.group,
+            fec,
+            udpts: self.udp,
+            batch,
+            rcvbuf: self.rcvbuf,
+            reorder_window: self.reorder_window.unwrap_or(if self.path_b.is_some() {
+                rtp::MERGE_DEFAULT_WINDOW
+            } else {
+                rtp::DEFAULT_WINDOW
+            }),
+            ssrc: self.ssrc,
+            sender,
+            path_b,
+            format,
+            timescale,
+        })
+    }
+
+    /// Resolves the address to bind to and the group to join, binding to the group address itself
+    /// where the `separate` sockets of several paths might otherwise receive one another's groups
+    fn path(&self, separate: bool) -> Result<Path, String> {
+        let group = self.group()?;
+        let addr: IpAddr = match (self.bind, &group) {
+            (Some(a), _) => a.parse().map_err(|_| "invalid bind address")?,
+            (None, Some(g)) if separate && cfg!(unix) => g.addr,
+            (
+                None,
+                Some(Group {
+                    addr: IpAddr::V6(_),
+                    ..
+                }),
+            ) => Ipv6Addr::UNSPECIFIED.into(),
+            (None, _) => Ipv4Addr::UNSPECIFIED.into(),
+        };
+        if let Some(ref group) = group {
+            if group.addr.is_ipv4() != addr.is_ipv4() {
+                return Err(
+                    "bind address and multicast group must both be IPv4 or both IPv6".into(),
+                );
+            }
+        }
+        Ok(Path {
+            addr: SocketAddr::new(addr, self.port),
+            group,
+        })
+    }
 
-fn fec(matches: &ArgMatches) -> Fec {
-    match matches.get_one::<String>("fec").map(AsRef::as_ref) {
-        Some("prompeg") => Fec::ProMpeg,
-        Some(other) => panic!("unsupported FEC mode {:?}", other),
-        None => Fec::None,
+    fn group(&self) -> Result<Option<Group>, String> {
+        let Some(mcast) = self.group else {
+            if self.ifaddr.is_some()
+                || self.ifindex.is_some()
+                || self.iface.is_some()
+                || !self.sources.is_empty()
+            {
+                return Err("--ifaddr, --ifindex, --iface and --source only apply when joining a multicast group with -m".into());
+            }
+            return Ok(None);
+        };
+        let addr: IpAddr = mcast
+            .parse()
+            .map_err(|_| "invalid multicast group address")?;
+        if !addr.is_multicast() {
+            return Err("-m address is not a multicast group".into());
+        }
+        let iface = self
+            .iface
+            .map(|name| iface::find(name, addr.is_ipv6()))
+            .transpose()?;
+        let ifaddr = match self.ifaddr {
+            Some(_) if addr.is_ipv6() => {
+                return Err(
+                    "--ifaddr only applies to IPv4 groups; use --ifindex or --iface for IPv6"
+                        .into(),
+                )
+            }
+            Some(a) => a.parse().map_err(|_| "invalid --ifaddr address")?,
+            None => iface
+                .iter()
+                .flat_map(|i| &i.addrs)
+                .find_map(|a| match a {
+                    IpAddr::V4(a) => Some(*a),
+                    IpAddr::V6(_) => None,
+                })
+                .unwrap_or(Ipv4Addr::UNSPECIFIED),
+        };
+        let ifindex = match self.ifindex {
+            Some(_) if addr.is_ipv4() => {
+                return Err("--ifindex only applies to IPv6 groups; use --ifaddr for IPv4".into())
+            }
+            Some(i) => match i.parse() {
+                Ok(index) => index,
+                Err(_) => iface::index(i).ok_or("--ifindex is not a known network interface")?,
+            },
+            None => iface.map_or(0, |i| i.index),
+        };
+        let mut sources = vec![];
+        for source in &self.sources {
+            let source: IpAddr = source.parse().map_err(|_| "invalid --source address")?;
+            if source.is_ipv4() != addr.is_ipv4() {
+                return Err(
+                    "--source addresses must be of the same IP version as the multicast group"
+                        .into(),
+                );
+            }
+            sources.push(source);
+        }
+        Ok(Some(Group {
+            addr,
+            ifaddr,
+            ifindex,
+            sources,
+        }))
     }
 }
 
-pub fn cli() -> Result<CommandSpec, &'static str> {
+pub fn cli() -> Result<CommandSpec, String> {
     let matches =
         Command::new("scte35dump")
             .author("David Holroyd")
@@ -82,14 +518,14 @@ pub fn cli() -> Result<CommandSpec, &'static str> {
                             .long("port")
                             .help("UDP port to bind to")
                             .num_args(1)
-                            .required(true),
+                            .required_unless_present("list-interfaces"),
                     )
                     .arg(
                         Arg::new("bind")
                             .short('b')
                             .long("bind")
                             .num_args(1)
-                            .help("IP address to bind to (defaults to 0.0.0.0)"),
+                            .help("IP address to bind to (defaults to 0.0.0.0, or :: for an IPv6 group)"),
                     )
                     .arg(
                         Arg::new("mcast")
@@ -99,20 +535,206 @@ pub fn cli() -> Result<CommandSpec, &'static str> {
                             .required(false),
                     )
                     .arg(Arg::new("ifaddr").long("ifaddr").num_args(1).help(
-                        "IP address of the network interface to be joined to a multicast group",
+                        "IP address of the network interface to be joined to an IPv4 multicast group",
+                    ))
+                    .arg(Arg::new("ifindex").long("ifindex").num_args(1).help(
+                        "Index or name of the network interface to be joined to an IPv6 multicast group",
                     ))
+                    .arg(
+                        Arg::new("iface")
+                            .long("iface")
+                            .num_args(1)
+                            .conflicts_with_all(["ifaddr", "ifindex"])
+                            .help("Name of the network interface to be joined to a multicast group, e.g. eth1"),
+                    )
+                    .arg(
+                        Arg::new("list-interfaces")
+                            .long("list-interfaces")
+                            .num_args(0)
+                            .exclusive(true)
+                            .help("List the network interfaces of this host, with their indexes and addresses"),
+                    )
+                    .arg(
+                        Arg::new("source")
+                            .long("source")
+                            .num_args(1)
+                            .action(clap::ArgAction::Append)
+                            .help("Make a source-specific join, receiving the group only from this sender (may be repeated)"),
+                    )
+                    .arg(
+                        Arg::new("batch")
+                            .long("batch")
+                            .num_args(1)
+                            .help(format!("Maximum number of datagrams to receive per system call, on Linux (default {}; 1 receives them one at a time)", BATCH_DEFAULT)),
+                    )
+                    .arg(
+                        Arg::new("rcvbuf")
+                            .long("rcvbuf")
+                            .num_args(1)
+                            .help("Size of the socket receive buffer to ask for, in bytes, to absorb bursts of packets"),
+                    )
+                    .arg(
+                        Arg::new("reorder-window")
+                            .long("reorder-window")
+                            .num_args(1)
+                            .help(format!("Number of RTP packets that may be held back waiting for a missing one to arrive out of order (default {}, at most {}; 0 passes packets on as they arrive)", rtp::DEFAULT_WINDOW, rtp::MAX_WINDOW)),
+                    )
+                    .arg(
+                        Arg::new("ssrc")
+                            .long("ssrc")
+                            .num_args(1)
+                            .help("Only process RTP packets with this SSRC (decimal, or hexadecimal with a 0x prefix), ignoring other senders"),
+                    )
+                    .arg(
+                        Arg::new("sender")
+                            .long("sender")
+                            .num_args(1)
+                            .help("Only process datagrams sent from this IP address, or address and port, ignoring other senders"),
+                    )
+                    .arg(
+                        Arg::new("mcast-b")
+                            .long("mcast-b")
+                            .num_args(1)
+                            .help("Multicast group carrying the second path of a SMPTE ST 2022-7 protected stream, whose packets are merged with those of the first"),
+                    )
+                    .arg(
+                        Arg::new("port-b")
+                            .long("port-b")
+                            .num_args(1)
+                            .help("UDP port to bind to for the second path (defaults to --port)"),
+                    )
+                    .arg(
+                        Arg::new("bind-b")
+                            .long("bind-b")
+                            .num_args(1)
+                            .help("IP address to bind to for the second path"),
+                    )
+                    .arg(
+                        Arg::new("ifaddr-b")
+                            .long("ifaddr-b")
+                            .num_args(1)
+                            .requires("mcast-b")
+                            .help("As --ifaddr, for the second path"),
+                    )
+                    .arg(
+                        Arg::new("ifindex-b")
+                            .long("ifindex-b")
+                            .num_args(1)
+                            .requires("mcast-b")
+                            .help("As --ifindex, for the second path"),
+                    )
+                    .arg(
+                        Arg::new("iface-b")
+                            .long("iface-b")
+                            .num_args(1)
+                            .requires("mcast-b")
+                            .conflicts_with_all(["ifaddr-b", "ifindex-b"])
+                            .help("As --iface, for the second path"),
+                    )
+                    .arg(
+                        Arg::new("source-b")
+                            .long("source-b")
+                            .num_args(1)
+                            .action(clap::ArgAction::Append)
+                            .requires("mcast-b")
+                            .help("As --source, for the second path"),
+                    )
                     .arg(
                         Arg::new("fec")
                             .long("fec")
                             .num_args(1)
                             .value_names(["prompeg"])
                             .help("Style of Forward Error Correction to apply (no FEC if omitted)"),
+                    )
+                    .args(format_args()),
+            )
+            .subcommand(
+                Command::new("monitor")
+                    .about("Read many network inputs at once, as listed in a configuration file")
+                    .arg(
+                        Arg::new("CONFIG")
+                            .help("A TOML file with an [[input]] table for each input")
+                            .required(true),
                     ),
             )
             .subcommand(
                 Command::new("file")
                     .about("Read a transport stream from the named file")
-                    .arg(Arg::new("NAME").required(true)),
+                    .arg(
+                        Arg::new("scan")
+                            .long("scan")
+                            .help("Look for SCTE-35 sections on every PID, even if PAT/PMT do not announce them")
+                            .num_args(0)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("start")
+                            .long("start")
+                            .num_args(1)
+                            .help("Where to start reading: a byte offset, seconds since the first PCR (e.g. '90s'), or 'pts:<90kHz value>'"),
+                    )
+                    .arg(
+                        Arg::new("end")
+                            .long("end")
+                            .num_args(1)
+                            .help("Where to stop reading, in the same forms as --start"),
+                    )
+                    .arg(
+                        Arg::new("max-cues")
+                            .long("max-cues")
+                            .num_args(1)
+                            .help("Stop after this many SCTE-35 sections have been found"),
+                    )
+                    .arg(
+                        Arg::new("follow")
+                            .short('f')
+                            .long("follow")
+                            .help("Keep reading as the file grows, like 'tail -f' (survives the file being truncated or replaced)")
+                            .num_args(0)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("jobs")
+                            .short('j')
+                            .long("jobs")
+                            .num_args(1)
+                            .help("Number of files to process in parallel, when several are given"),
+                    )
+                    .args(format_args())
+                    .arg(
+                        Arg::new("NAME")
+                            .help("Transport stream files, directories containing .ts files, or glob patterns")
+                            .num_args(1..)
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("hls")
+                    .about("Decode the SCTE-35 signalling in the tags of a local HLS playlist")
+                    .arg(
+                        Arg::new("NAME")
+                            .help("An HLS .m3u8 playlist file")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("xml")
+                    .about("Decode the SCTE-35 signalling in a DASH MPD's EventStreams, or in a SCTE 35 XML document")
+                    .arg(
+                        Arg::new("NAME")
+                            .help("An MPD or SCTE 35 XML file")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("mp4")
+                    .about("Decode the SCTE-35 sections carried in the emsg boxes of fragmented MP4 / CMAF segments")
+                    .arg(
+                        Arg::new("NAME")
+                            .help("Segment files, directories containing segments, or glob patterns")
+                            .num_args(1..)
+                            .required(true),
+                    ),
             )
             .subcommand(
                 Command::new("section")
@@ -131,6 +753,7 @@ pub fn cli() -> Result<CommandSpec, &'static str> {
                             .num_args(0)
                             .required(false),
                     )
+                    .args(format_args())
                     .arg(
                         Arg::new("SECTION")
                             .help("A SCTE-35 splice_info section value")
@@ -140,42 +763,80 @@ pub fn cli() -> Result<CommandSpec, &'static str> {
             .get_matches();
 
     let cmd = if let Some(matches) = matches.subcommand_matches("net") {
-        let addr = match matches.get_one::<String>("bind") {
-            Some(a) => a,
-            None => "0.0.0.0",
-        };
-        let udp = matches.get_flag("udp");
-        CommandSpec::Net(NetCmd {
-            addr: SocketAddr::new(
-                addr.parse().map_err(|_| "invalid bind address")?,
-                matches
-                    .get_one::<String>("port")
-                    .unwrap()
-                    .parse::<u16>()
-                    .map_err(|_| "invalid port")?,
-            ),
-            group: group(matches),
-            fec: fec(matches),
-            udpts: udp,
+        if matches.get_flag("list-interfaces") {
+            return Ok(CommandSpec::ListInterfaces);
+        }
+        let (format, timescale) = output_format(matches)?;
+        CommandSpec::Net(NetOptions::from_matches(matches)?.net_cmd(format, timescale)?)
+    } else if let Some(matches) = matches.subcommand_matches("monitor") {
+        CommandSpec::Monitor(MonitorCmd {
+            config: matches.get_one::<String>("CONFIG").unwrap().to_string(),
         })
     } else if let Some(matches) = matches.subcommand_matches("file") {
+        let names: Vec<String> = matches
+            .get_many::<String>("NAME")
+            .unwrap()
+            .map(|s| s.to_string())
+            .collect();
+        let follow = matches.get_flag("follow");
+        let (format, timescale) = output_format(matches)?;
         CommandSpec::File(FileCmd {
+            names,
+            jobs: matches
+                .get_one::<String>("jobs")
+                .map(|s| s.parse().map_err(|_| "invalid --jobs value"))
+                .transpose()?
+                .unwrap_or(1),
+            scan: matches.get_flag("scan"),
+            start: matches
+                .get_one::<String>("start")
+                .map(|s| s.parse())
+                .transpose()?,
+            end: matches
+                .get_one::<String>("end")
+                .map(|s| s.parse())
+                .transpose()?,
+            max_cues: matches
+                .get_one::<String>("max-cues")
+                .map(|s| s.parse().map_err(|_| "invalid --max-cues value"))
+                .transpose()?,
+            follow,
+            format,
+            timescale,
+        })
+    } else if let Some(matches) = matches.subcommand_matches("hls") {
+        CommandSpec::Hls(HlsCmd {
             name: matches.get_one::<String>("NAME").unwrap().to_string(),
         })
+    } else if let Some(matches) = matches.subcommand_matches("xml") {
+        CommandSpec::Xml(XmlCmd {
+            name: matches.get_one::<String>("NAME").unwrap().to_string(),
+        })
+    } else if let Some(matches) = matches.subcommand_matches("mp4") {
+        CommandSpec::Mp4(Mp4Cmd {
+            names: matches
+                .get_many::<String>("NAME")
+                .unwrap()
+                .map(|s| s.to_string())
+                .collect(),
+        })
     } else if let Some(matches) = matches.subcommand_matches("section") {
         let enc = if matches.get_flag("hex") {
             SectEncoding::Hex
         } else if matches.get_flag("base64") {
             SectEncoding::Base64
         } else {
-            return Err("Either --hex or --base64 must be specified");
+            return Err("Either --hex or --base64 must be specified".into());
         };
+        let (format, timescale) = output_format(matches)?;
         CommandSpec::Section(SectCmd {
             value: matches.get_one::<String>("SECTION").unwrap().to_string(),
             encoding: enc,
+            format,
+            timescale,
         })
     } else {
-        return Err("subcommand must be specified");
+        return Err("subcommand must be specified".into());
     };
 
     Ok(cmd)
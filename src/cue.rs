@@ -0,0 +1,135 @@
+//! A simplified view of a `splice_info_section()`, capturing what is needed to render it as
+//! signalling for other formats, like HLS playlist tags.
+
+/// PTS values are 33-bit quantities
+pub const PTS_MODULUS: u64 = 1 << 33;
+
+/// Segmentation types (not all known to `SegmentationTypeId`) which start an opportunity to
+/// replace content, and so map to leaving the network.  The following type ends the opportunity.
+const OUT_TYPES: &[u8] = &[
+    0x22, 0x30, 0x32, 0x34, 0x36, 0x38, 0x3a, 0x3c, 0x3e, 0x40, 0x44, 0x46,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// leaving the network, e.g. the start of an ad break
+    Out,
+    /// returning to the network
+    In,
+    /// any other command, such as a `splice_null()` heartbeat or cancellation
+    Other,
+}
+
+pub struct Cue {
+    /// the `splice_event_id` or `segmentation_event_id`, if any
+    pub id: Option<u32>,
+    pub kind: Kind,
+    /// the splice time, with `pts_adjustment` applied, if not immediate
+    pub pts: Option<u64>,
+    /// the duration of the break in 90kHz units, if signalled
+    pub duration: Option<u64>,
+    /// the whole `splice_info_section()`, including CRC
+    pub section: Vec<u8>,
+}
+impl Cue {
+    pub fn new(
+        header: &scte35_reader::SpliceInfoHeader<'_>,
+        command: &scte35_reader::SpliceCommand,
+        descriptors: &scte35_reader::SpliceDescriptors<'_>,
+        section: Vec<u8>,
+    ) -> Cue {
+        let adjust = |pts: u64| (pts + header.pts_adjustment()) % PTS_MODULUS;
+        let mut cue = Cue {
+            id: None,
+            kind: Kind::Other,
+            pts: None,
+            duration: None,
+            section,
+        };
+        match command {
+            scte35_reader::SpliceCommand::SpliceInsert {
+                splice_event_id,
+                splice_detail,
+                ..
+            } => {
+                cue.id = Some(*splice_event_id);
+                if let scte35_reader::SpliceInsert::Insert {
+                    network_indicator,
+                    splice_mode,
+                    duration,
+                    ..
+                } = splice_detail
+                {
+                    cue.kind = match network_indicator {
+                        scte35_reader::NetworkIndicator::Out => Kind::Out,
+                        scte35_reader::NetworkIndicator::In => Kind::In,
+                    };
+                    if let scte35_reader::SpliceMode::Program(scte35_reader::SpliceTime::Timed(
+                        Some(pts),
+                    )) = splice_mode
+                    {
+                        cue.pts = Some(adjust(*pts));
+                    }
+                    cue.duration = duration.as_ref().and_then(splice_duration);
+                }
+            }
+            scte35_reader::SpliceCommand::TimeSignal {
+                splice_time: scte35_reader::SpliceTime::Timed(Some(pts)),
+            } => {
+                cue.pts = Some(adjust(*pts));
+                for desc in descriptors.into_iter().flatten() {
+                    if let scte35_reader::SpliceDescriptor::SegmentationDescriptor {
+                        segmentation_event_id,
+                        descriptor_detail:
+                            scte35_reader::SegmentationDescriptor::Insert {
+                                segmentation_type_id,
+                                segmentation_duration,
+                                ..
+                            },
+                    } = desc
+                    {
+                        cue.kind = match segmentation_kind(&segmentation_type_id) {
+                            Kind::Other => continue,
+                            kind => kind,
+                        };
+                        cue.id = Some(segmentation_event_id);
+                        cue.duration = segmentation_duration;
+                        break;
+                    }
+                }
+            }
+            _ => (),
+        }
+        cue
+    }
+}
+
+fn segmentation_kind(type_id: &scte35_reader::SegmentationTypeId) -> Kind {
+    use scte35_reader::SegmentationTypeId::*;
+    match *type_id {
+        BreakStart
+        | ProviderAdvertisementStart
+        | DistributorAdvertisementStart
+        | ProviderPlacementOpportunityStart
+        | DistributorPlacementOpportunityStart
+        | UnscheduledEventStart => Kind::Out,
+        BreakEnd
+        | ProviderAdvertisementEnd
+        | DistributorAdvertisementEnd
+        | ProviderPlacementOpportunityEnd
+        | DistributorPlacementOpportunityEnd
+        | UnscheduledEventEnd => Kind::In,
+        Reserved(id) if OUT_TYPES.contains(&id) => Kind::Out,
+        Reserved(id) if OUT_TYPES.contains(&id.wrapping_sub(1)) => Kind::In,
+        _ => Kind::Other,
+    }
+}
+
+/// The `duration` field of `break_duration()`, which the `SpliceDuration` type does not expose
+/// other than through its serde implementation
+fn splice_duration(duration: &scte35_reader::SpliceDuration) -> Option<u64> {
+    serde_json::to_value(duration)
+        .ok()?
+        .get("duration")?
+        .as_u64()
+}
@@ -0,0 +1,77 @@
+//! Rendering of cues as DASH MPD `EventStream` elements, per SCTE 214-1
+
+use crate::cue::Cue;
+use crate::xml;
+use base64::Engine as _;
+
+pub const SCHEME_XML: &str = "urn:scte:scte35:2013:xml";
+pub const SCHEME_XML_BIN: &str = "urn:scte:scte35:2014:xml+bin";
+
+/// How the section is carried within each `Event`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scheme {
+    /// as `scte35:SpliceInfoSection` markup
+    Xml,
+    /// as base64 within `scte35:Signal/scte35:Binary`
+    XmlBin,
+}
+
+/// Renders a cue as an `EventStream` containing a single `Event`.
+///
+/// `presentationTime` is the splice time (or `now`, for immediate splices), converted to the
+/// given timescale.  `fallback_id` is used as the `Event` id for commands which have no event id.
+#[allow(clippy::too_many_arguments)]
+pub fn event_stream(
+    scheme: Scheme,
+    timescale: u64,
+    cue: &Cue,
+    fallback_id: u64,
+    now: Option<u64>,
+    header: &scte35_reader::SpliceInfoHeader<'_>,
+    command: &scte35_reader::SpliceCommand,
+    descriptors: &scte35_reader::SpliceDescriptors<'_>,
+) -> String {
+    let mut w = xml::Writer::new(0);
+    let scheme_id_uri = match scheme {
+        Scheme::Xml => SCHEME_XML,
+        Scheme::XmlBin => SCHEME_XML_BIN,
+    };
+    w.open(
+        "EventStream",
+        &[
+            ("schemeIdUri", scheme_id_uri.to_string()),
+            ("timescale", timescale.to_string()),
+        ],
+    );
+    let rescale = |ticks: u64| (u128::from(ticks) * u128::from(timescale) / 90_000) as u64;
+    let mut attrs = vec![(
+        "presentationTime",
+        rescale(cue.pts.or(now).unwrap_or(0)).to_string(),
+    )];
+    if let Some(duration) = cue.duration {
+        attrs.push(("duration", rescale(duration).to_string()));
+    }
+    attrs.push((
+        "id",
+        cue.id.map(u64::from).unwrap_or(fallback_id).to_string(),
+    ));
+    w.open("Event", &attrs);
+    match scheme {
+        Scheme::Xml => xml::splice_info_section(&mut w, header, command, descriptors),
+        Scheme::XmlBin => {
+            w.open(
+                "scte35:Signal",
+                &[("xmlns:scte35", xml::NAMESPACE.to_string())],
+            );
+            w.text(
+                "scte35:Binary",
+                &[],
+                &base64::engine::general_purpose::STANDARD.encode(&cue.section),
+            );
+            w.close("scte35:Signal");
+        }
+    }
+    w.close("Event");
+    w.close("EventStream");
+    w.finish()
+}
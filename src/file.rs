@@ -0,0 +1,418 @@
+use crate::cli;
+use crate::hls;
+use crate::mpegts;
+use mpeg2ts_reader::demultiplex;
+use mpeg2ts_reader::packet;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const PACKET_SIZE: u64 = packet::Packet::SIZE as u64;
+/// How much data to examine when looking for a PCR at some position in the file; at 80Mbps
+/// this covers a bit over 300ms, allowing for the 100ms maximum PCR interval
+const PCR_SEARCH_WINDOW: usize = packet::Packet::SIZE * 20_000;
+/// How long `--follow` waits before checking for more data once the end of the file is reached
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// PCR base values wrap around after 2^33 ticks of the 90kHz clock
+const PCR_BASE_MODULUS: u64 = 1 << 33;
+
+const READ_BUFFER_SIZE: usize = packet::Packet::SIZE * 10 * 1024;
+
+/// File name extensions of transport stream files to be picked up when a directory is given
+const TS_EXTENSIONS: &[&str] = &["ts", "m2ts", "mts", "trp"];
+
+/// Outcome of processing one of the input files
+struct FileSummary {
+    cues: u64,
+    error: Option<io::Error>,
+}
+
+pub fn main(cmd: &cli::FileCmd) -> Result<(), io::Error> {
+    let inputs = expand_inputs(&cmd.names, TS_EXTENSIONS)?;
+    // checked once directories and globs are expanded, since any of them may give several files
+    if cmd.follow && inputs.len() > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--follow can only be used with a single file",
+        ));
+    }
+    if let [ref name] = inputs[..] {
+        return process(cmd, name, None).map(|_| ());
+    }
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<FileSummary>>> =
+        inputs.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..cmd.jobs.clamp(1, inputs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(name) = inputs.get(i) else {
+                    break;
+                };
+                let summary = match process(cmd, name, Some(name)) {
+                    Ok(cues) => FileSummary { cues, error: None },
+                    Err(e) => {
+                        println!("[{}] error: {}", name, e);
+                        FileSummary {
+                            cues: 0,
+                            error: Some(e),
+                        }
+                    }
+                };
+                *results[i].lock().unwrap() = Some(summary);
+            });
+        }
+    });
+    let results: Vec<_> = results
+        .into_iter()
+        .map(|r| r.into_inner().unwrap().expect("all inputs processed"))
+        .collect();
+    println!(
+        "Summary: {} files, {} cues",
+        inputs.len(),
+        results.iter().map(|r| r.cues).sum::<u64>()
+    );
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    for (name, result) in inputs.iter().zip(results) {
+        match result.error {
+            Some(e) => println!(" - {}: error: {}", name, e),
+            None => println!(" - {}: {} cues", name, result.cues),
+        }
+    }
+    if failed > 0 {
+        // so that scripts processing many files notice any that failed
+        return Err(io::Error::other(format!(
+            "{} of {} files could not be processed",
+            failed,
+            inputs.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Expands directories and glob patterns given on the command line into a list of files, taking
+/// those with one of the given extensions from any directories
+pub fn expand_inputs(names: &[String], extensions: &[&str]) -> Result<Vec<String>, io::Error> {
+    let mut result = vec![];
+    for name in names {
+        let path = Path::new(name);
+        if path.is_dir() {
+            let mut files = vec![];
+            for entry in std::fs::read_dir(path)? {
+                let path = entry?.path();
+                let wanted = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| extensions.contains(&e.to_ascii_lowercase().as_str()));
+                if wanted && path.is_file() {
+                    files.push(path.to_string_lossy().into_owned());
+                }
+            }
+            files.sort();
+            result.extend(files);
+        } else if !path.exists() && name.contains(['*', '?', '[']) {
+            let paths =
+                glob::glob(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            for path in paths {
+                let path = path?;
+                if path.is_file() {
+                    result.push(path.to_string_lossy().into_owned());
+                }
+            }
+        } else {
+            result.push(name.to_string());
+        }
+    }
+    if result.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no input files matched",
+        ));
+    }
+    Ok(result)
+}
+
+fn new_context(cmd: &cli::FileCmd, label: Option<&str>) -> mpegts::DumpDemuxContext {
+    let mut ctx = if cmd.scan {
+        mpegts::DumpDemuxContext::new_scanning()
+    } else {
+        mpegts::DumpDemuxContext::new()
+    };
+    if let Some(label) = label {
+        ctx.set_label(label);
+    }
+    if let Some(max_cues) = cmd.max_cues {
+        ctx.set_max_cues(max_cues);
+    }
+    ctx.set_format(cmd.format, cmd.timescale);
+    ctx
+}
+
+/// Dumps the SCTE-35 data in a single file (or in the segments listed by an HLS playlist),
+/// returning the number of cues found
+fn process(cmd: &cli::FileCmd, name: &str, label: Option<&str>) -> Result<u64, io::Error> {
+    if is_playlist(name) {
+        return process_playlist(cmd, name, label);
+    }
+    let mut f = File::open(name)?;
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    let mut ctx = new_context(cmd, label);
+    let file_len = f.metadata()?.len();
+    let mut clock = None;
+    if let Some(ref end) = cmd.end {
+        let end = resolve(&mut f, file_len, end, &mut clock)?;
+        ctx.set_end_offset(end);
+    }
+    if let Some(ref start) = cmd.start {
+        let start = resolve(&mut f, file_len, start, &mut clock)?;
+        let start = sync_from(&mut f, start)?;
+        f.seek(SeekFrom::Start(start))?;
+        ctx.set_position(mpegts::PacketPosition {
+            index: start / PACKET_SIZE,
+            offset: start,
+        });
+    }
+    let mut demux = demultiplex::Demultiplex::new(&mut ctx);
+    let mut len = 0;
+    while !ctx.is_done() {
+        match f.read(&mut buf[len..])? {
+            0 if cmd.follow => {
+                thread::sleep(FOLLOW_POLL_INTERVAL);
+                if let Some(reopened) = check_replaced(name, &mut f)? {
+                    f = reopened;
+                    len = 0;
+                    ctx.set_position(mpegts::PacketPosition::default());
+                    demux = demultiplex::Demultiplex::new(&mut ctx);
+                }
+            }
+            0 => break,
+            n => len = push_read(&mut demux, &mut ctx, &mut buf, len + n),
+        }
+    }
+    if let Some(scan) = ctx.scan_results() {
+        scan.print_summary(label);
+    }
+    Ok(ctx.cue_count())
+}
+
+fn is_playlist(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("m3u8"))
+}
+
+/// Dumps the SCTE-35 data in the segments of an HLS media playlist, treating them as one
+/// continuous stream so that PSI and continuity are carried over from one segment to the next
+fn process_playlist(cmd: &cli::FileCmd, name: &str, label: Option<&str>) -> Result<u64, io::Error> {
+    if cmd.start.is_some() || cmd.end.is_some() || cmd.follow {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--start, --end and --follow are not supported for playlists",
+        ));
+    }
+    let segments = hls::media_segments(&std::fs::read_to_string(name)?)?;
+    let base = Path::new(name).parent().unwrap_or(Path::new(""));
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    let mut ctx = new_context(cmd, label);
+    let mut demux = demultiplex::Demultiplex::new(&mut ctx);
+    let mut len = 0;
+    for segment in segments {
+        if ctx.is_done() {
+            break;
+        }
+        let mut f = File::open(base.join(&segment.uri))?;
+        let mut f: Box<dyn Read> = match segment.byte_range {
+            Some((range_len, offset)) => {
+                f.seek(SeekFrom::Start(offset))?;
+                Box::new(f.take(range_len))
+            }
+            None => Box::new(f),
+        };
+        if segment.discontinuity {
+            ctx.discontinuity();
+        }
+        ctx.start_segment(&segment.uri);
+        while !ctx.is_done() {
+            match f.read(&mut buf[len..])? {
+                0 => break,
+                n => len = push_read(&mut demux, &mut ctx, &mut buf, len + n),
+            }
+        }
+        if len != 0 {
+            println!(
+                "{}segment {} does not end on a packet boundary",
+                ctx.prefix(),
+                segment.uri
+            );
+            // the next segment starts with a packet of its own, so the remainder is of no use
+            len = 0;
+        }
+    }
+    if let Some(scan) = ctx.scan_results() {
+        scan.print_summary(label);
+    }
+    Ok(ctx.cue_count())
+}
+
+/// Passes the whole packets among the first `len` bytes of the buffer to the demultiplexer,
+/// moving any partial packet that remains to the start of the buffer to be completed by the
+/// next `read()`.  Returns the length of the remaining partial packet.
+fn push_read(
+    demux: &mut demultiplex::Demultiplex<mpegts::DumpDemuxContext>,
+    ctx: &mut mpegts::DumpDemuxContext,
+    buf: &mut [u8],
+    len: usize,
+) -> usize {
+    let whole = len - len % packet::Packet::SIZE;
+    mpegts::push(demux, ctx, &buf[..whole]);
+    buf.copy_within(whole..len, 0);
+    len - whole
+}
+
+/// If the file at the given path has been truncated or replaced (e.g. by log-rotation style
+/// renaming) since we opened it, returns a newly opened handle, positioned at the start
+fn check_replaced(name: &str, f: &mut File) -> Result<Option<File>, io::Error> {
+    let pos = f.stream_position()?;
+    let current = f.metadata()?;
+    if current.len() < pos {
+        println!("{}: file truncated, reading again from the start", name);
+        let mut f = File::open(name)?;
+        f.seek(SeekFrom::Start(0))?;
+        return Ok(Some(f));
+    }
+    match std::fs::metadata(name) {
+        Ok(latest) if !same_file(&current, &latest) => {
+            println!("{}: file replaced, reading new file from the start", name);
+            File::open(name).map(Some)
+        }
+        // the file may briefly not exist while being rotated; we'll check again next time
+        _ => Ok(None),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+#[cfg(not(unix))]
+fn same_file(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+    // no portable way to check; rely on detecting truncation
+    true
+}
+
+/// The first PCR in the file, against which time-based positions are measured
+struct Clock {
+    pid: packet::Pid,
+    first: u64,
+}
+impl Clock {
+    fn elapsed(&self, pcr_base: u64) -> u64 {
+        (pcr_base + PCR_BASE_MODULUS - self.first) % PCR_BASE_MODULUS
+    }
+}
+
+/// Converts the given position into a byte offset within the file, searching for the position
+/// by PCR value if required
+fn resolve(
+    f: &mut File,
+    file_len: u64,
+    pos: &cli::Position,
+    clock: &mut Option<Clock>,
+) -> Result<u64, io::Error> {
+    let target = match *pos {
+        cli::Position::Offset(offset) => return Ok(offset.min(file_len)),
+        cli::Position::Seconds(secs) => (secs * 90_000.0) as u64,
+        cli::Position::Pts(pts) => {
+            let clock = first_clock(f, clock)?;
+            clock.elapsed(pts % PCR_BASE_MODULUS)
+        }
+    };
+    let clock = first_clock(f, clock)?;
+    // binary search for the last packet at which the PCR has not yet passed the target
+    let mut lo = 0;
+    let mut hi = file_len / PACKET_SIZE;
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        match pcr_from(f, mid * PACKET_SIZE, Some(clock.pid))? {
+            Some((_, pcr)) if clock.elapsed(pcr) <= target => lo = mid,
+            _ => hi = mid,
+        }
+    }
+    Ok(lo * PACKET_SIZE)
+}
+
+fn first_clock<'c>(f: &mut File, clock: &'c mut Option<Clock>) -> Result<&'c Clock, io::Error> {
+    if clock.is_none() {
+        match pcr_from(f, 0, None)? {
+            Some((pid, first)) => *clock = Some(Clock { pid, first }),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no PCR found near the start of the file, so cannot seek by time",
+                ))
+            }
+        }
+    }
+    Ok(clock.as_ref().unwrap())
+}
+
+/// Finds the first PCR on the given PID (or on any PID, if `None`) at or after the given offset,
+/// looking no further than `PCR_SEARCH_WINDOW`.  Returns the PID and the 90kHz PCR base value.
+fn pcr_from(
+    f: &mut File,
+    offset: u64,
+    pid: Option<packet::Pid>,
+) -> Result<Option<(packet::Pid, u64)>, io::Error> {
+    let buf = read_at(f, offset, PCR_SEARCH_WINDOW)?;
+    let start = match find_sync(&buf) {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    for data in buf[start..].chunks_exact(packet::Packet::SIZE) {
+        let pk = match packet::Packet::try_new(data) {
+            Some(pk) => pk,
+            None => continue,
+        };
+        if pid.is_some_and(|pid| pid != pk.pid()) {
+            continue;
+        }
+        if let Some(af) = pk.adaptation_field() {
+            if let Ok(pcr) = af.pcr() {
+                return Ok(Some((pk.pid(), pcr.base())));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the offset of the first packet at or after the given offset, which may differ if the
+/// offset was not packet-aligned, or if the file has junk before the first packet
+fn sync_from(f: &mut File, offset: u64) -> Result<u64, io::Error> {
+    let buf = read_at(f, offset, packet::Packet::SIZE * 8)?;
+    Ok(offset + find_sync(&buf).unwrap_or(0) as u64)
+}
+
+fn read_at(f: &mut File, offset: u64, len: usize) -> Result<Vec<u8>, io::Error> {
+    f.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::with_capacity(len);
+    f.take(len as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Finds the first offset in the buffer where a sync byte starts a run of consecutive packets
+fn find_sync(buf: &[u8]) -> Option<usize> {
+    (0..packet::Packet::SIZE.min(buf.len())).find(|&i| {
+        buf[i..]
+            .iter()
+            .step_by(packet::Packet::SIZE)
+            .take(3)
+            .all(|&b| packet::Packet::is_sync_byte(b))
+    })
+}
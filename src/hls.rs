@@ -0,0 +1,423 @@
+//! Support for HTTP Live Streaming playlists: reading the segments and cue tags of playlists, and
+//! rendering cues as playlist tags
+
+use crate::cli;
+use crate::cue::{Cue, Kind, PTS_MODULUS};
+use crate::mpegts;
+use base64::Engine as _;
+use std::io;
+
+/// A media segment listed in an HLS media playlist
+#[derive(Debug, PartialEq)]
+pub struct MediaSegment {
+    pub uri: String,
+    /// the `(length, offset)` of the part of the resource making up the segment, if it is given
+    /// by `EXT-X-BYTERANGE` rather than being the whole resource
+    pub byte_range: Option<(u64, u64)>,
+    /// set if `EXT-X-DISCONTINUITY` comes before the segment, so that timestamps and continuity
+    /// counters may not follow on from the segment before
+    pub discontinuity: bool,
+}
+
+/// Lists the segments of the given media playlist, in order
+pub fn media_segments(playlist: &str) -> Result<Vec<MediaSegment>, io::Error> {
+    let mut lines = playlist.lines().map(str::trim);
+    if lines.next().map(|l| l.trim_start_matches('\u{feff}')) != Some("#EXTM3U") {
+        return Err(invalid("playlist does not start with #EXTM3U"));
+    }
+    let mut segments = vec![];
+    let mut byte_range = None;
+    let mut discontinuity = false;
+    // where the previous sub-range of each resource ended, for byte ranges with no offset
+    let mut last_range: Option<(&str, u64)> = None;
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(tag) = line.strip_prefix('#') {
+            if let Some(value) = tag.strip_prefix("EXT-X-BYTERANGE:") {
+                byte_range = Some(parse_byte_range(value)?);
+            } else if tag == "EXT-X-DISCONTINUITY" {
+                discontinuity = true;
+            } else if tag.starts_with("EXT-X-STREAM-INF:") {
+                return Err(invalid(
+                    "this is a master playlist; give the media playlist of one of its variants",
+                ));
+            }
+            continue;
+        }
+        if line.contains("://") {
+            return Err(invalid(format!(
+                "segment {} is not a local file, which is not supported",
+                line
+            )));
+        }
+        let byte_range = byte_range.take().map(|(len, offset)| {
+            let offset = offset.unwrap_or(match last_range {
+                Some((uri, end)) if uri == line => end,
+                _ => 0,
+            });
+            last_range = Some((line, offset + len));
+            (len, offset)
+        });
+        segments.push(MediaSegment {
+            uri: line.to_string(),
+            byte_range,
+            discontinuity: std::mem::take(&mut discontinuity),
+        });
+    }
+    Ok(segments)
+}
+
+/// Parses the `<n>[@<o>]` value of an `EXT-X-BYTERANGE` tag
+fn parse_byte_range(value: &str) -> Result<(u64, Option<u64>), io::Error> {
+    let (len, offset) = match value.split_once('@') {
+        Some((len, offset)) => (len, Some(offset)),
+        None => (value, None),
+    };
+    let bad = |_| invalid(format!("invalid EXT-X-BYTERANGE {:?}", value));
+    let len = len.parse().map_err(bad)?;
+    let offset = offset.map(str::parse).transpose().map_err(bad)?;
+    Ok((len, offset))
+}
+
+fn invalid<E>(msg: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Renders a cue as an `EXT-X-DATERANGE` tag, with the original section given in hex in the
+/// `SCTE35-OUT`, `SCTE35-IN` or `SCTE35-CMD` attribute.
+///
+/// There is no wall-clock time to hand, so `START-DATE` is the splice time (or `now` for
+/// immediate splices) counted from the Unix epoch, which at least places date ranges correctly
+/// relative to one another.  `fallback_id` is used as the `ID` for commands which have no event
+/// id.
+pub fn daterange(cue: &Cue, fallback_id: u64, now: Option<u64>) -> String {
+    let id = match cue.id {
+        Some(id) => id.to_string(),
+        None => format!("cmd-{}", fallback_id),
+    };
+    let start = cue.pts.or(now).unwrap_or(0);
+    let mut tag = format!(
+        "#EXT-X-DATERANGE:ID=\"{}\",START-DATE=\"{}\"",
+        id,
+        date(start)
+    );
+    if let (Kind::Out, Some(duration)) = (cue.kind, cue.duration) {
+        tag.push_str(&format!(",PLANNED-DURATION={}", seconds(duration)));
+    }
+    let attr = match cue.kind {
+        Kind::Out => "SCTE35-OUT",
+        Kind::In => "SCTE35-IN",
+        Kind::Other => "SCTE35-CMD",
+    };
+    tag.push_str(&format!(
+        ",{}=0x{}\n",
+        attr,
+        hex::encode_upper(&cue.section)
+    ));
+    tag
+}
+
+struct ActiveBreak {
+    /// 90kHz time at which the break started
+    start: u64,
+    duration: Option<u64>,
+    section: String,
+}
+
+/// Renders cues in the `EXT-X-CUE-OUT` / `EXT-X-CUE-IN` dialect, with the original section
+/// given in an `EXT-OATCLS-SCTE35` tag, tracking any break in progress so that
+/// `EXT-X-CUE-OUT-CONT` can be produced at the start of each following segment
+#[derive(Default)]
+pub struct CueOut {
+    active: Option<ActiveBreak>,
+}
+impl CueOut {
+    /// The tags for the given cue.  `now` is the current 90kHz time, used for splices that are
+    /// to happen immediately.
+    pub fn cue(&mut self, cue: &Cue, now: Option<u64>) -> String {
+        let section = base64::engine::general_purpose::STANDARD.encode(&cue.section);
+        let mut tags = format!("#EXT-OATCLS-SCTE35:{}\n", section);
+        match cue.kind {
+            Kind::Out => {
+                match cue.duration {
+                    Some(duration) => {
+                        tags.push_str(&format!("#EXT-X-CUE-OUT:{}\n", seconds(duration)))
+                    }
+                    None => tags.push_str("#EXT-X-CUE-OUT\n"),
+                }
+                self.active = Some(ActiveBreak {
+                    start: cue.pts.or(now).unwrap_or(0),
+                    duration: cue.duration,
+                    section,
+                });
+            }
+            Kind::In => {
+                tags.push_str("#EXT-X-CUE-IN\n");
+                self.active = None;
+            }
+            Kind::Other => (),
+        }
+        tags
+    }
+
+    /// The tag to be placed before a segment starting at the given 90kHz time, if a break is in
+    /// progress; `EXT-X-CUE-IN` if the break has reached the end of its duration
+    pub fn segment_start(&mut self, now: u64) -> Option<String> {
+        let active = self.active.as_ref()?;
+        let elapsed = (now + PTS_MODULUS - active.start) % PTS_MODULUS;
+        if elapsed > PTS_MODULUS / 2 {
+            // splice time not yet reached
+            return None;
+        }
+        match active.duration {
+            Some(duration) if elapsed >= duration => {
+                self.active = None;
+                Some("#EXT-X-CUE-IN\n".to_string())
+            }
+            Some(duration) => Some(format!(
+                "#EXT-X-CUE-OUT-CONT:ElapsedTime={},Duration={},SCTE35={}\n",
+                seconds(elapsed),
+                seconds(duration),
+                active.section
+            )),
+            None => Some(format!(
+                "#EXT-X-CUE-OUT-CONT:ElapsedTime={},SCTE35={}\n",
+                seconds(elapsed),
+                active.section
+            )),
+        }
+    }
+}
+
+/// Formats a 90kHz duration as decimal seconds
+fn seconds(ticks: u64) -> String {
+    format!("{}.{:03}", ticks / 90_000, ticks % 90_000 / 90)
+}
+
+/// Formats a 90kHz time, counted from the Unix epoch, as an ISO 8601 date
+fn date(ticks: u64) -> String {
+    let secs = ticks / 90_000;
+    let millis = ticks % 90_000 / 90;
+    // days to civil date conversion from http://howardhinnant.github.io/date_algorithms.html
+    let days = secs / 86_400;
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs % 86_400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        millis
+    )
+}
+
+/// Reports the SCTE-35 signalling found in the tags of a local playlist, decoding any embedded
+/// `splice_info_section()`
+pub fn main(cmd: &cli::HlsCmd) -> Result<(), io::Error> {
+    let playlist = std::fs::read_to_string(&cmd.name)?;
+    let mut timeline = Timeline::default();
+    for (i, line) in playlist.lines().enumerate() {
+        let line = line.trim();
+        let tag = match line.strip_prefix('#') {
+            Some(tag) => tag,
+            None => {
+                if !line.is_empty() {
+                    timeline.segment_end();
+                }
+                continue;
+            }
+        };
+        let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+        let context = match timeline.program_date_time() {
+            Some(pdt) => format!("line {}, {}", i + 1, pdt),
+            None => format!("line {}", i + 1),
+        };
+        match name {
+            "EXT-X-PROGRAM-DATE-TIME" => match parse_date(value) {
+                Some(pdt) => timeline.set_program_date_time(pdt),
+                None => println!("[line {}] invalid {} {:?}", i + 1, name, value),
+            },
+            "EXTINF" => {
+                let duration = value.split(',').next().unwrap_or("");
+                match duration.trim().parse::<f64>() {
+                    Ok(duration) if duration.is_finite() && duration >= 0.0 => {
+                        timeline.duration = duration
+                    }
+                    _ => println!("[line {}] invalid {} {:?}", i + 1, name, value),
+                }
+            }
+            "EXT-X-DATERANGE" => {
+                let attrs = attributes(value);
+                let id = attrs.iter().find(|(k, _)| *k == "ID").map(|(_, v)| *v);
+                for (attr, v) in &attrs {
+                    if let "SCTE35-OUT" | "SCTE35-IN" | "SCTE35-CMD" = *attr {
+                        let label =
+                            format!("{}, {} ID={:?} {}", context, name, id.unwrap_or(""), attr);
+                        let hex = v.trim_start_matches("0x").trim_start_matches("0X");
+                        dump(&label, hex::decode(hex).map_err(|e| e.to_string()));
+                    }
+                }
+            }
+            "EXT-X-SCTE35" => {
+                for (attr, v) in attributes(value) {
+                    if attr == "CUE" {
+                        dump(&format!("{}, {} {}", context, name, attr), decode_base64(v));
+                    }
+                }
+            }
+            "EXT-OATCLS-SCTE35" => dump(&format!("{}, {}", context, name), decode_base64(value)),
+            "EXT-X-CUE-OUT" | "EXT-X-CUE-IN" => println!("[{}] {}", context, line),
+            "EXT-X-CUE-OUT-CONT" => {
+                println!("[{}] {}", context, line);
+                for (attr, v) in attributes(value) {
+                    if attr == "SCTE35" {
+                        dump(&format!("{}, {} {}", context, name, attr), decode_base64(v));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .map_err(|e| e.to_string())
+}
+
+fn dump(label: &str, data: Result<Vec<u8>, String>) {
+    let result = data.and_then(|data| {
+        mpegts::dump_section(
+            &data,
+            mpegts::DumpSpliceInfoProcessor {
+                label: Some(label.into()),
+                ..mpegts::DumpSpliceInfoProcessor::new(None)
+            },
+        )
+    });
+    if let Err(e) = result {
+        println!("[{}] {}", label, e);
+    }
+}
+
+/// Splits an attribute list (RFC 8216 section 4.2) into names and values, with the quotes
+/// removed from quoted-string values
+fn attributes(list: &str) -> Vec<(&str, &str)> {
+    let mut result = vec![];
+    let mut rest = list.trim();
+    while let Some((name, tail)) = rest.split_once('=') {
+        let (value, tail) = match tail.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, tail)) => (value, tail),
+                None => (quoted, ""),
+            },
+            None => tail.split_once(',').unwrap_or((tail, "")),
+        };
+        result.push((name.trim(), value));
+        rest = tail.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+    result
+}
+
+/// Tracks `EXT-X-PROGRAM-DATE-TIME` and `EXTINF` values, to give the date at which each segment
+/// starts
+#[derive(Default)]
+struct Timeline {
+    /// milliseconds since the Unix epoch given by the most recent `EXT-X-PROGRAM-DATE-TIME`
+    date: Option<u64>,
+    /// seconds of media in the segments since that tag
+    elapsed: f64,
+    /// duration of the upcoming segment
+    duration: f64,
+}
+impl Timeline {
+    fn set_program_date_time(&mut self, date: u64) {
+        self.date = Some(date);
+        self.elapsed = 0.0;
+    }
+    fn segment_end(&mut self) {
+        self.elapsed += self.duration;
+        self.duration = 0.0;
+    }
+    /// The date at which the upcoming segment starts, unless it is too far in the future to
+    /// represent
+    fn program_date_time(&self) -> Option<String> {
+        let millis = self
+            .date?
+            .checked_add((self.elapsed * 1000.0).round() as u64)?;
+        Some(self::date(millis.checked_mul(90)?))
+    }
+}
+
+/// Parses an ISO 8601 date of the form used by `EXT-X-PROGRAM-DATE-TIME`, returning milliseconds
+/// since the Unix epoch
+fn parse_date(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (date, time) = value.split_once(['T', 't'])?;
+    let mut ymd = date.splitn(3, '-').map(|v| v.parse::<u64>().ok());
+    let (year, month, day) = (ymd.next()??, ymd.next()??, ymd.next()??);
+    // split off any timezone designator
+    let (time, offset_secs) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0i64)
+    } else if let Some(i) = time.rfind(['+', '-']) {
+        let (time, tz) = time.split_at(i);
+        let sign = if tz.starts_with('-') { -1 } else { 1 };
+        let tz = tz[1..].replace(':', "");
+        if tz.len() != 4 {
+            return None;
+        }
+        let hours: i64 = tz[..2].parse().ok()?;
+        let mins: i64 = tz[2..].parse().ok()?;
+        (time, sign * (hours * 3600 + mins * 60))
+    } else {
+        (time, 0)
+    };
+    let mut hms = time.splitn(3, ':');
+    let hours: u64 = hms.next()?.parse().ok()?;
+    let mins: u64 = hms.next()?.parse().ok()?;
+    let secs: f64 = hms.next()?.parse().ok()?;
+    // bounding each field also keeps the arithmetic below from overflowing
+    if year > 9999 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // allowing for a leap second
+    if hours > 23 || mins > 59 || !(0.0..61.0).contains(&secs) {
+        return None;
+    }
+    // civil date to days conversion from http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 {
+        year.checked_sub(1)?
+    } else {
+        year
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146_097 + doe).checked_sub(719_468)?;
+    let millis = ((days * 86_400 + hours * 3600 + mins * 60) * 1000) as i64
+        + (secs * 1000.0).round() as i64
+        - offset_secs * 1000;
+    if millis < 0 {
+        return None;
+    }
+    Some(millis as u64)
+}
@@ -0,0 +1,129 @@
+//! Lookup of network interfaces, for choosing where multicast groups are joined
+
+use std::io;
+use std::net::IpAddr;
+
+pub struct Interface {
+    pub name: String,
+    pub index: u32,
+    pub addrs: Vec<IpAddr>,
+}
+
+/// The index of the interface with the given name, if it exists
+#[cfg(unix)]
+pub fn index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // safety: the name is a valid NUL-terminated string, which if_nametoindex() only reads
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index),
+    }
+}
+#[cfg(not(unix))]
+pub fn index(_name: &str) -> Option<u32> {
+    // no portable way to look up names; indexes may still be given numerically
+    None
+}
+
+/// All the network interfaces of this host with their IP addresses, ordered by index
+#[cfg(unix)]
+pub fn interfaces() -> Result<Vec<Interface>, io::Error> {
+    let mut result: Vec<Interface> = vec![];
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // safety: on success getifaddrs() gives a linked list, which we only read before freeing it
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut next = addrs;
+    // safety: each entry is either null, ending the list, or points to an ifaddrs allocated by
+    // getifaddrs(), which stays valid until freeifaddrs() below
+    while let Some(ifa) = unsafe { next.as_ref() } {
+        next = ifa.ifa_next;
+        // safety: ifa_name is a NUL-terminated string belonging to the list, which is copied
+        // before the list is freed
+        let name = unsafe { std::ffi::CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        // safety: ifa_addr is null or points to a sockaddr of the family it declares, sized
+        // accordingly, which is valid until the list is freed
+        let addr = unsafe { ip_addr(ifa.ifa_addr) };
+        let i = match result.iter().position(|i| i.name == name) {
+            Some(i) => i,
+            None => {
+                let index = index(&name).unwrap_or(0);
+                result.push(Interface {
+                    name,
+                    index,
+                    addrs: vec![],
+                });
+                result.len() - 1
+            }
+        };
+        result[i].addrs.extend(addr);
+    }
+    // safety: addrs came from a successful getifaddrs(), and nothing borrowed from the list
+    // outlives this call
+    unsafe { libc::freeifaddrs(addrs) };
+    result.sort_by_key(|i| i.index);
+    Ok(result)
+}
+#[cfg(not(unix))]
+pub fn interfaces() -> Result<Vec<Interface>, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing network interfaces is not supported on this platform",
+    ))
+}
+
+/// Converts an IPv4 or IPv6 socket address, ignoring other address families (such as the link
+/// layer addresses that Linux also reports)
+///
+/// safety: `addr` must be null or point to a valid `sockaddr` of the family it declares
+#[cfg(unix)]
+unsafe fn ip_addr(addr: *const libc::sockaddr) -> Option<IpAddr> {
+    match i32::from(addr.as_ref()?.sa_family) {
+        libc::AF_INET => {
+            let sin = &*(addr as *const libc::sockaddr_in);
+            Some(std::net::Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)).into())
+        }
+        libc::AF_INET6 => {
+            let sin6 = &*(addr as *const libc::sockaddr_in6);
+            Some(std::net::Ipv6Addr::from(sin6.sin6_addr.s6_addr).into())
+        }
+        _ => None,
+    }
+}
+
+/// Prints each interface with its index and addresses, for `net --list-interfaces`
+pub fn list() -> Result<(), io::Error> {
+    for i in interfaces()? {
+        let addrs: Vec<_> = i.addrs.iter().map(ToString::to_string).collect();
+        println!("{:>3} {:<16} {}", i.index, i.name, addrs.join(" "));
+    }
+    Ok(())
+}
+
+/// Finds the interface of the given name, for joining a group of the given IP version on it
+///
+/// IPv4 groups are joined using an address of the interface, and IPv6 groups using its index, but
+/// either way the interface must have an address of the group's IP version to be usable.
+pub fn find(name: &str, ipv6: bool) -> Result<Interface, String> {
+    let interfaces = interfaces().map_err(|e| format!("listing network interfaces: {}", e))?;
+    let interface = interfaces
+        .into_iter()
+        .find(|i| i.name == name)
+        .ok_or_else(|| {
+            format!(
+                "no network interface named {:?} (see --list-interfaces)",
+                name
+            )
+        })?;
+    if !interface.addrs.iter().any(|a| a.is_ipv6() == ipv6) {
+        return Err(format!(
+            "network interface {:?} has no {} address",
+            name,
+            if ipv6 { "IPv6" } else { "IPv4" }
+        ));
+    }
+    Ok(interface)
+}
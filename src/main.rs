@@ -1,33 +1,22 @@
 #![deny(rust_2018_idioms, future_incompatible)]
 
-use mpeg2ts_reader::psi::WholeCompactSyntaxPayloadParser;
-use std::fs::File;
-use std::io::Read;
-
+#[cfg(target_os = "linux")]
+mod batch;
 mod cli;
+mod cue;
+mod dash;
+mod file;
+mod hls;
+mod iface;
+mod monitor;
+mod mp4;
 mod mpegts;
 mod net;
+mod pcr;
+mod rtp;
+mod xml;
 
 use base64::Engine as _;
-use mpeg2ts_reader::demultiplex;
-use mpeg2ts_reader::psi;
-use std::cell;
-use std::rc;
-
-fn file_main(cmd: &cli::FileCmd) -> Result<(), std::io::Error> {
-    let mut f = File::open(&cmd.name).unwrap_or_else(|_| panic!("Problem reading {}", cmd.name));
-    let mut buf = vec![0u8; 1880 * 1024];
-    let mut ctx = mpegts::DumpDemuxContext::new();
-    let mut demux = demultiplex::Demultiplex::new(&mut ctx);
-    loop {
-        match f.read(&mut buf[..])? {
-            0 => break,
-            // TODO: if not all bytes are consumed, track buf remainder
-            n => demux.push(&mut ctx, &buf[0..n]),
-        }
-    }
-    Ok(())
-}
 
 fn section_main(cmd: &cli::SectCmd) -> Result<(), String> {
     let data = match cmd.encoding {
@@ -40,14 +29,21 @@ fn section_main(cmd: &cli::SectCmd) -> Result<(), String> {
         cli::SectEncoding::Hex => hex::decode(cmd.value.as_bytes())
             .map_err(|e| format!("hex decoding problem: {:?}", e))?,
     };
-    let mut parser = scte35_reader::Scte35SectionProcessor::new(mpegts::DumpSpliceInfoProcessor {
-        elementary_pid: None,
-        last_pcr: rc::Rc::new(cell::Cell::new(None)),
-    });
-    let header = psi::SectionCommonHeader::new(&data[..psi::SectionCommonHeader::SIZE]);
-    let mut ctx = mpegts::DumpDemuxContext::new();
-    parser.section(&mut ctx, &header, &data[..]);
-    Ok(())
+    mpegts::dump_section(
+        &data,
+        mpegts::DumpSpliceInfoProcessor {
+            format: cmd.format,
+            timescale: cmd.timescale,
+            ..mpegts::DumpSpliceInfoProcessor::new(None)
+        },
+    )
+}
+/// Reports an error from one of the subcommands, and exits with a non-zero status
+fn exit_on_error<E: std::fmt::Display>(result: Result<(), E>) {
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        ::std::process::exit(1);
+    }
 }
 fn main() {
     env_logger::init();
@@ -59,7 +55,12 @@ fn main() {
         Ok(cli::CommandSpec::Net(cmd)) => {
             net::main(&cmd);
         }
-        Ok(cli::CommandSpec::File(cmd)) => file_main(&cmd).expect("file"),
+        Ok(cli::CommandSpec::File(cmd)) => exit_on_error(file::main(&cmd)),
+        Ok(cli::CommandSpec::Hls(cmd)) => exit_on_error(hls::main(&cmd)),
+        Ok(cli::CommandSpec::Xml(cmd)) => exit_on_error(xml::main(&cmd)),
+        Ok(cli::CommandSpec::Mp4(cmd)) => exit_on_error(mp4::main(&cmd)),
+        Ok(cli::CommandSpec::ListInterfaces) => iface::list().expect("list interfaces"),
+        Ok(cli::CommandSpec::Monitor(cmd)) => exit_on_error(monitor::main(&cmd)),
         Ok(cli::CommandSpec::Section(cmd)) => {
             section_main(&cmd).expect("section");
         }
@@ -0,0 +1,374 @@
+//! Monitoring of many network inputs at once, as listed in a configuration file, all handled on a
+//! single `mio` event loop
+
+use crate::cli;
+use crate::mpegts;
+use crate::net;
+use mpeg2ts_reader::demultiplex;
+use smpte2022_1_fec::heap_pool::HeapPool;
+use smpte2022_1_fec::BufferPool;
+use smpte2022_1_fec::Decoder;
+use smpte2022_1_fec::Packet;
+use std::collections::HashSet;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Each input has a main socket, plus column and row sockets if FEC is in use, with tokens
+/// allocated in blocks of this size
+const SOCKETS_PER_INPUT: usize = 3;
+/// Longest wait for datagrams before checking on the inputs anyway, so that those which have gone
+/// quiet are still noticed
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long an input may go without datagrams on its main socket before it is reported as silent
+const SILENCE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How soon a socket which gave an error is read from again, to pick up any datagrams behind it
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+/// Errors from an input's sockets after the first are reported at most this often
+const ERROR_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// as for `--format`, applying to all inputs
+    format: Option<String>,
+    timescale: Option<u64>,
+    #[serde(rename = "input", default)]
+    inputs: Vec<InputConfig>,
+}
+
+/// Settings for one input, named after the corresponding `net` subcommand options
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InputConfig {
+    label: String,
+    port: u16,
+    bind: Option<String>,
+    group: Option<String>,
+    ifaddr: Option<String>,
+    ifindex: Option<String>,
+    iface: Option<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default)]
+    udp: bool,
+    fec: Option<String>,
+    rcvbuf: Option<usize>,
+    reorder_window: Option<usize>,
+    ssrc: Option<Ssrc>,
+    sender: Option<String>,
+}
+impl InputConfig {
+    fn net_cmd(&self, format: cli::OutputFormat, timescale: u64) -> Result<cli::NetCmd, String> {
+        let ssrc = match self.ssrc {
+            Some(Ssrc::Number(ssrc)) => Some(ssrc),
+            Some(Ssrc::Text(ref ssrc)) => Some(cli::parse_ssrc(ssrc)?),
+            None => None,
+        };
+        cli::NetOptions {
+            port: self.port,
+            bind: self.bind.as_deref(),
+            group: self.group.as_deref(),
+            ifaddr: self.ifaddr.as_deref(),
+            ifindex: self.ifindex.as_deref(),
+            iface: self.iface.as_deref(),
+            sources: self.sources.iter().map(String::as_str).collect(),
+            udp: self.udp,
+            fec: self.fec.as_deref(),
+            // inputs are read through the event loop, one datagram at a time
+            batch: Some("1"),
+            rcvbuf: self.rcvbuf,
+            reorder_window: self.reorder_window,
+            ssrc,
+            sender: self.sender.as_deref(),
+            path_b: None,
+        }
+        .net_cmd(format, timescale)
+    }
+}
+
+/// An SSRC given either as a TOML integer, or as a string which may be in hex like `"0x1234abcd"`,
+/// the way that SSRCs are printed
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Ssrc {
+    Number(u32),
+    Text(String),
+}
+
+/// How the datagrams arriving for an input are processed
+enum Handler {
+    /// transport stream packets directly in UDP
+    Udp {
+        ctx: mpegts::DumpDemuxContext,
+        demux: demultiplex::Demultiplex<mpegts::DumpDemuxContext>,
+    },
+    Rtp(net::RtpReceiver),
+    Fec {
+        pool: HeapPool,
+        decoder: Decoder<HeapPool, net::RtpReceiver>,
+        /// datagrams discarded since the pool ran out of packet buffers, reported once some are
+        /// free again
+        starved: u64,
+    },
+}
+
+struct Input {
+    /// the label of the input, formatted to prefix each line of output relating to it
+    prefix: String,
+    /// the main socket, followed by the FEC column and row sockets, if any
+    sockets: Vec<mio::net::UdpSocket>,
+    drops: Vec<net::DropMonitor>,
+    /// filters for the main socket, and the FEC sockets (which are matched on address alone)
+    filters: [net::SenderFilter; 2],
+    handler: Handler,
+    /// when the last datagram arrived on the main socket (or when monitoring started)
+    last_datagram: Instant,
+    /// whether the input has been reported as having gone silent
+    silent: bool,
+    /// when an error was last reported, and how many have been left unreported since
+    last_error: Option<Instant>,
+    unreported_errors: u64,
+}
+impl Input {
+    fn new(config: &InputConfig, cmd: &cli::NetCmd) -> Result<Input, io::Error> {
+        let mut ports = vec![cmd.addr.port()];
+        if let cli::Fec::ProMpeg = cmd.fec {
+            ports.push(cmd.addr.port() + 2);
+            ports.push(cmd.addr.port() + 4);
+        }
+        let mut sockets = vec![];
+        let mut drops = vec![];
+        for port in ports {
+            let sock = net::create_socket(cmd, port)?;
+            drops.extend(net::DropMonitor::new(&sock));
+            sock.set_nonblocking(true)?;
+            sockets.push(mio::net::UdpSocket::from_std(sock));
+        }
+        let mut ctx = net::new_context(cmd);
+        ctx.set_label(&config.label);
+        let prefix = ctx.prefix();
+        let handler = if cmd.udpts {
+            let demux = demultiplex::Demultiplex::new(&mut ctx);
+            Handler::Udp { ctx, demux }
+        } else if let cli::Fec::ProMpeg = cmd.fec {
+            let pool = HeapPool::new(net::PACKET_COUNT_MAX, net::PACKET_SIZE_MAX);
+            let decoder = Decoder::new(pool.clone(), net::RtpReceiver::new(ctx, cmd));
+            Handler::Fec {
+                pool,
+                decoder,
+                starved: 0,
+            }
+        } else {
+            Handler::Rtp(net::RtpReceiver::new(ctx, cmd))
+        };
+        let filters = [
+            net::SenderFilter::new(cmd.sender, prefix.clone()),
+            net::SenderFilter::new(cmd.sender.map(cli::Sender::any_port), prefix.clone()),
+        ];
+        Ok(Input {
+            prefix,
+            filters,
+            sockets,
+            drops,
+            handler,
+            last_datagram: Instant::now(),
+            silent: false,
+            last_error: None,
+            unreported_errors: 0,
+        })
+    }
+
+    /// Reads from the socket at the given index until it has no more datagrams, returning
+    /// `false` if it stopped at an error, in which case the socket should be read from again
+    /// later since there may be datagrams behind it
+    fn drain(&mut self, socket: usize, buf: &mut [u8]) -> bool {
+        loop {
+            match self.sockets[socket].recv_from(buf) {
+                Ok((size, from)) => {
+                    if self.filters[socket.min(1)].accept(from) {
+                        if socket == 0 {
+                            self.active(Instant::now());
+                        }
+                        self.datagram(socket, &buf[..size])
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) => {
+                    // not read again right away, since an error that persists would otherwise
+                    // keep us from ever getting to the other inputs
+                    self.recv_error(e);
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn recv_error(&mut self, e: io::Error) {
+        let now = Instant::now();
+        if self
+            .last_error
+            .is_some_and(|last| now - last < ERROR_REPORT_INTERVAL)
+        {
+            self.unreported_errors += 1;
+            return;
+        }
+        if self.unreported_errors > 0 {
+            println!(
+                "{}recv() error: {:?} ({} more errors since the last reported)",
+                self.prefix, e, self.unreported_errors
+            );
+        } else {
+            println!("{}recv() error: {:?}", self.prefix, e);
+        }
+        self.last_error = Some(now);
+        self.unreported_errors = 0;
+    }
+
+    /// Notes the arrival of a datagram on the main socket
+    fn active(&mut self, now: Instant) {
+        if self.silent {
+            println!(
+                "{}datagrams arriving again, after {:.1}s without any",
+                self.prefix,
+                (now - self.last_datagram).as_secs_f64()
+            );
+            self.silent = false;
+        }
+        self.last_datagram = now;
+    }
+
+    /// Makes the periodic checks on the input, whether or not it is receiving anything
+    fn tick(&mut self, now: Instant) {
+        for d in &mut self.drops {
+            d.check(&self.prefix);
+        }
+        let idle = now - self.last_datagram;
+        if !self.silent && idle >= SILENCE_TIMEOUT {
+            println!(
+                "{}no datagrams received for {:.1}s",
+                self.prefix,
+                idle.as_secs_f64()
+            );
+            self.silent = true;
+        }
+    }
+
+    /// Handles a datagram received on the socket at the given index
+    fn datagram(&mut self, socket: usize, data: &[u8]) {
+        match self.handler {
+            Handler::Udp {
+                ref mut ctx,
+                ref mut demux,
+            } => {
+                ctx.set_receive_time(Instant::now());
+                mpegts::push(demux, ctx, data);
+            }
+            Handler::Rtp(ref mut recv) => recv.packet(data, Instant::now()),
+            Handler::Fec {
+                ref pool,
+                ref mut decoder,
+                ref mut starved,
+            } => {
+                let Some(mut pk) = pool.allocate() else {
+                    if *starved == 0 {
+                        println!(
+                            "{}FEC: no free packet buffers, discarding datagrams",
+                            self.prefix
+                        );
+                    }
+                    *starved += 1;
+                    return;
+                };
+                if *starved > 0 {
+                    println!(
+                        "{}FEC: packet buffers free again, after discarding {} datagrams",
+                        self.prefix, starved
+                    );
+                    *starved = 0;
+                }
+                let size = data.len().min(pk.payload().len());
+                pk.payload_mut()[..size].copy_from_slice(&data[..size]);
+                pk.truncate(size);
+                let packets = vec![pk].into_iter();
+                let result = match socket {
+                    0 => decoder.add_main_packets(packets),
+                    1 => decoder.add_column_packets(packets),
+                    _ => decoder.add_row_packets(packets),
+                };
+                if let Err(e) = result {
+                    println!("{}FEC: {:?}", self.prefix, e);
+                }
+            }
+        }
+    }
+}
+
+pub fn main(cmd: &cli::MonitorCmd) -> Result<(), io::Error> {
+    let text = std::fs::read_to_string(&cmd.config)?;
+    let config: Config = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    if config.inputs.is_empty() {
+        return Err(invalid(format!("no [[input]] given in {}", cmd.config)));
+    }
+    let format = match config.format {
+        Some(ref f) => f.parse().map_err(invalid)?,
+        None => cli::OutputFormat::Dump,
+    };
+    let timescale = match config.timescale {
+        Some(0) => return Err(invalid("invalid timescale value")),
+        Some(t) => t,
+        None => 90_000,
+    };
+
+    let mut labels = HashSet::new();
+    let mut inputs = vec![];
+    for input in &config.inputs {
+        if !labels.insert(&input.label) {
+            return Err(invalid(format!("duplicate input label {:?}", input.label)));
+        }
+        let net_cmd = input
+            .net_cmd(format, timescale)
+            .map_err(|e| invalid(format!("input {:?}: {}", input.label, e)))?;
+        let input = Input::new(input, &net_cmd)
+            .map_err(|e| io::Error::new(e.kind(), format!("input {:?}: {}", input.label, e)))?;
+        inputs.push(input);
+    }
+
+    let mut poll = mio::Poll::new()?;
+    for (i, input) in inputs.iter_mut().enumerate() {
+        for (s, sock) in input.sockets.iter_mut().enumerate() {
+            let token = mio::Token(i * SOCKETS_PER_INPUT + s);
+            poll.registry()
+                .register(sock, token, mio::Interest::READABLE)?;
+        }
+    }
+    let mut buf = vec![0; 9000];
+    let mut events = mio::Events::with_capacity(1024);
+    // tokens of the sockets to read from again, after they gave an error
+    let mut retry = vec![];
+    loop {
+        let timeout = if retry.is_empty() {
+            TICK_INTERVAL
+        } else {
+            RETRY_DELAY
+        };
+        poll.poll(&mut events, Some(timeout))?;
+        let mut ready: Vec<_> = events.iter().map(|e| e.token().0).collect();
+        ready.append(&mut retry);
+        ready.sort_unstable();
+        ready.dedup();
+        for token in ready {
+            let input = &mut inputs[token / SOCKETS_PER_INPUT];
+            if !input.drain(token % SOCKETS_PER_INPUT, &mut buf) {
+                retry.push(token);
+            }
+        }
+        let now = Instant::now();
+        for input in &mut inputs {
+            input.tick(now);
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
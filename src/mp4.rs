@@ -0,0 +1,238 @@
+//! Extraction of SCTE-35 sections from the `emsg` (event message) boxes of fragmented MP4 / CMAF
+//! segments, per SCTE 214-3 and ISO/IEC 23009-1
+
+use crate::cli;
+use crate::file;
+use crate::mpegts;
+use std::io;
+
+/// The scheme identifying `emsg` boxes whose `message_data` is a binary `splice_info_section()`
+const SCHEME_BIN: &str = "urn:scte:scte35:2013:bin";
+
+/// File name extensions of segment files to be picked up when a directory is given
+const MP4_EXTENSIONS: &[&str] = &["mp4", "m4s", "m4v", "m4a", "cmfv", "cmfa", "cmft"];
+
+/// `event_duration` value signalling that the duration is unknown
+const UNKNOWN_DURATION: u32 = 0xffff_ffff;
+
+pub fn main(cmd: &cli::Mp4Cmd) -> Result<(), io::Error> {
+    let inputs = file::expand_inputs(&cmd.names, MP4_EXTENSIONS)?;
+    if let [ref name] = inputs[..] {
+        return process(name, None);
+    }
+    let mut failed = 0;
+    for name in &inputs {
+        if let Err(e) = process(name, Some(name)) {
+            println!("[{}] error: {}", name, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(io::Error::other(format!(
+            "{} of {} files could not be processed",
+            failed,
+            inputs.len()
+        )));
+    }
+    Ok(())
+}
+
+fn process(name: &str, label: Option<&str>) -> Result<(), io::Error> {
+    let data = std::fs::read(name)?;
+    let mut offset = 0;
+    while offset < data.len() {
+        let b = Mp4Box::parse(&data[offset..]).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} at offset {}", e, offset),
+            )
+        })?;
+        if &b.box_type == b"emsg" {
+            let mut desc = String::new();
+            if let Some(label) = label {
+                desc.push_str(label);
+                desc.push_str(", ");
+            }
+            desc.push_str(&format!("offset {}", offset));
+            match Emsg::parse(b.payload) {
+                Ok(emsg) => emsg.dump(&desc),
+                Err(e) => println!("[{}] invalid emsg box: {}", desc, e),
+            }
+        }
+        offset += b.size;
+    }
+    Ok(())
+}
+
+struct Mp4Box<'a> {
+    box_type: [u8; 4],
+    /// the size of the whole box, including its header
+    size: usize,
+    payload: &'a [u8],
+}
+impl<'a> Mp4Box<'a> {
+    fn parse(data: &'a [u8]) -> Result<Mp4Box<'a>, String> {
+        let mut r = Reader::new(data);
+        let size = r.u32().map_err(|_| "truncated box header")?;
+        let mut box_type = [0; 4];
+        box_type.copy_from_slice(r.bytes(4).map_err(|_| "truncated box header")?);
+        let size = match size {
+            // the box extends to the end of the file
+            0 => data.len() as u64,
+            1 => r.u64().map_err(|_| "truncated box header")?,
+            size => u64::from(size),
+        };
+        let header = r.pos;
+        if size < header as u64 || size > data.len() as u64 {
+            return Err(format!(
+                "{:?} box size {} is invalid, with {} bytes remaining",
+                String::from_utf8_lossy(&box_type),
+                size,
+                data.len()
+            ));
+        }
+        let size = size as usize;
+        Ok(Mp4Box {
+            box_type,
+            size,
+            payload: &data[header..size],
+        })
+    }
+}
+
+/// The fields of an `emsg` box
+struct Emsg<'a> {
+    version: u8,
+    scheme_id_uri: String,
+    value: String,
+    timescale: u32,
+    /// `presentation_time_delta` for version 0 boxes (relative to the earliest presentation time
+    /// of the segment), or `presentation_time` for version 1 boxes
+    time: u64,
+    event_duration: u32,
+    id: u32,
+    message_data: &'a [u8],
+}
+impl<'a> Emsg<'a> {
+    fn parse(data: &'a [u8]) -> Result<Emsg<'a>, &'static str> {
+        let mut r = Reader::new(data);
+        let version = r.u32()?.to_be_bytes()[0];
+        match version {
+            0 => {
+                let scheme_id_uri = r.string()?;
+                let value = r.string()?;
+                Ok(Emsg {
+                    version,
+                    scheme_id_uri,
+                    value,
+                    timescale: r.u32()?,
+                    time: u64::from(r.u32()?),
+                    event_duration: r.u32()?,
+                    id: r.u32()?,
+                    message_data: r.rest(),
+                })
+            }
+            1 => {
+                let timescale = r.u32()?;
+                let time = r.u64()?;
+                let event_duration = r.u32()?;
+                let id = r.u32()?;
+                Ok(Emsg {
+                    version,
+                    scheme_id_uri: r.string()?,
+                    value: r.string()?,
+                    timescale,
+                    time,
+                    event_duration,
+                    id,
+                    message_data: r.rest(),
+                })
+            }
+            _ => Err("unsupported emsg version"),
+        }
+    }
+
+    fn dump(&self, desc: &str) {
+        let mut label = format!("{}, emsg v{} id={}", desc, self.version, self.id);
+        let time_name = if self.version == 0 {
+            "presentation_time_delta"
+        } else {
+            "presentation_time"
+        };
+        label.push_str(&format!(" {}={}", time_name, self.time));
+        if self.timescale > 0 {
+            label.push_str(&format!(
+                " ({:.3}s)",
+                self.time as f64 / f64::from(self.timescale)
+            ));
+        }
+        if self.event_duration == UNKNOWN_DURATION {
+            label.push_str(" duration=unknown");
+        } else {
+            label.push_str(&format!(" duration={}", self.event_duration));
+            if self.timescale > 0 {
+                label.push_str(&format!(
+                    " ({:.3}s)",
+                    f64::from(self.event_duration) / f64::from(self.timescale)
+                ));
+            }
+        }
+        label.push_str(&format!(" timescale={}", self.timescale));
+        if self.scheme_id_uri != SCHEME_BIN {
+            println!(
+                "[{}] skipping scheme_id_uri={:?} value={:?}",
+                label, self.scheme_id_uri, self.value
+            );
+            return;
+        }
+        let result = mpegts::dump_section(
+            self.message_data,
+            mpegts::DumpSpliceInfoProcessor {
+                label: Some(label.as_str().into()),
+                ..mpegts::DumpSpliceInfoProcessor::new(None)
+            },
+        );
+        if let Err(e) = result {
+            println!("[{}] {}", label, e);
+        }
+    }
+}
+
+/// Reads big-endian fields from box payloads
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        let end = self.pos + len;
+        let bytes = self.data.get(self.pos..end).ok_or("truncated emsg box")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn u64(&mut self) -> Result<u64, &'static str> {
+        Ok(u64::from(self.u32()?) << 32 | u64::from(self.u32()?))
+    }
+    /// A null-terminated UTF-8 string
+    fn string(&mut self) -> Result<String, &'static str> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("unterminated string")?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos..];
+        self.pos = self.data.len();
+        rest
+    }
+}
@@ -1,15 +1,107 @@
+use crate::cli::OutputFormat;
+use crate::cue::Cue;
+use crate::dash;
+use crate::hls;
+use crate::xml;
 use mpeg2ts_reader::demultiplex;
+use mpeg2ts_reader::demultiplex::DemuxContext;
 use mpeg2ts_reader::packet;
 use mpeg2ts_reader::packet::Pid;
 use mpeg2ts_reader::psi;
 
 use std::cell;
 use std::collections::HashMap;
+use std::fmt::Write;
 use std::rc::Rc;
+use std::time::Instant;
+
+/// The `table_id` value of a SCTE-35 `splice_info_section()`
+const SPLICE_INFO_TABLE_ID: u8 = 0xfc;
+
+/// Location of a TS packet within the input
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PacketPosition {
+    /// count of packets preceding this one
+    pub index: u64,
+    /// byte offset of the start of this packet
+    pub offset: u64,
+}
+
+/// The positions of the first and last TS packets that carried a section
+pub type SectionSpan = Rc<cell::Cell<Option<(PacketPosition, PacketPosition)>>>;
+
+/// One of a sequence of files (e.g. HLS segments) that together make up the input
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub uri: Rc<str>,
+    /// position of the first packet of the segment within the input as a whole
+    pub start: PacketPosition,
+}
+
+/// The segments making up the input so far, in order, if the input is segmented
+pub type Segments = Rc<cell::RefCell<Vec<Segment>>>;
 
 pub struct DumpSpliceInfoProcessor {
     pub elementary_pid: Option<Pid>,
     pub last_pcr: Rc<cell::Cell<Option<packet::ClockRef>>>,
+    /// set when packets have been lost on the PID since the last section was processed
+    pub discontinuity: Rc<cell::Cell<bool>>,
+    /// where in the input the section being processed was found, if it came from a TS
+    pub span: SectionSpan,
+    /// count of sections processed, possibly shared with processors for other PIDs
+    pub cues: Rc<cell::Cell<u64>>,
+    /// identifies the input, when output from several inputs is combined
+    pub label: Option<Rc<str>>,
+    /// allows section positions to be reported relative to the containing segment
+    pub segments: Segments,
+    pub format: OutputFormat,
+    /// units per second of times given in `OutputFormat::DashXml` and `DashXmlBin` output
+    pub timescale: u64,
+    /// the whole of the section being processed, for formats that include the original data
+    pub raw: Rc<cell::RefCell<Vec<u8>>>,
+    /// break state for `OutputFormat::HlsCue`, shared with the context for segment boundaries
+    pub cue_out: Rc<cell::RefCell<hls::CueOut>>,
+}
+impl DumpSpliceInfoProcessor {
+    pub fn new(elementary_pid: Option<Pid>) -> Self {
+        DumpSpliceInfoProcessor {
+            elementary_pid,
+            last_pcr: Rc::new(cell::Cell::new(None)),
+            discontinuity: Rc::new(cell::Cell::new(false)),
+            span: Rc::new(cell::Cell::new(None)),
+            cues: Rc::new(cell::Cell::new(0)),
+            label: None,
+            segments: Default::default(),
+            format: OutputFormat::Dump,
+            timescale: 90_000,
+            raw: Default::default(),
+            cue_out: Default::default(),
+        }
+    }
+
+    /// The positions of the packets carrying the section being processed
+    fn take_span(&self) -> Option<(PacketPosition, PacketPosition)> {
+        let span = self.span.get();
+        if let Some((_, last)) = span {
+            // any further section completed within the same packet must also have started there
+            self.span.set(Some((last, last)));
+        }
+        span
+    }
+
+    /// Describes the segment containing the given position, and the position relative to the
+    /// start of that segment
+    fn segment_position(&self, pos: PacketPosition) -> Option<String> {
+        let segments = self.segments.borrow();
+        let i = segments.partition_point(|s| s.start.offset <= pos.offset);
+        let segment = segments.get(i.checked_sub(1)?)?;
+        Some(format!(
+            "segment {} packet {} (offset {}) ",
+            segment.uri,
+            pos.index - segment.start.index,
+            pos.offset - segment.start.offset
+        ))
+    }
 }
 impl scte35_reader::SpliceInfoProcessor for DumpSpliceInfoProcessor {
     fn process(
@@ -18,13 +110,74 @@ impl scte35_reader::SpliceInfoProcessor for DumpSpliceInfoProcessor {
         command: scte35_reader::SpliceCommand,
         descriptors: scte35_reader::SpliceDescriptors<'_>,
     ) {
+        self.cues.set(self.cues.get() + 1);
+        // the record is assembled before printing so that it is written out in one go, and
+        // doesn't get interleaved with output from other threads
+        let mut out = String::new();
+        let label = label_prefix(self.label.as_deref());
+        if self.discontinuity.replace(false) {
+            let note = format!(
+                "{:?}: packets were lost before this section, so earlier cues may be missing",
+                self.elementary_pid.unwrap_or(mpeg2ts_reader::STUFFING_PID)
+            );
+            let _ = writeln!(out, "{}{}", label, self.format.comment(&note));
+        }
+        let span = self.take_span();
+        if self.format != OutputFormat::Dump {
+            let cue = Cue::new(&header, &command, &descriptors, self.raw.borrow().clone());
+            let now = self.last_pcr.get().map(|pcr| pcr.base());
+            let tags = match self.format {
+                OutputFormat::HlsDaterange => hls::daterange(&cue, self.cues.get(), now),
+                OutputFormat::HlsCue => self.cue_out.borrow_mut().cue(&cue, now),
+                OutputFormat::DashXml | OutputFormat::DashXmlBin => dash::event_stream(
+                    if self.format == OutputFormat::DashXml {
+                        dash::Scheme::Xml
+                    } else {
+                        dash::Scheme::XmlBin
+                    },
+                    self.timescale,
+                    &cue,
+                    self.cues.get(),
+                    now,
+                    &header,
+                    &command,
+                    &descriptors,
+                ),
+                OutputFormat::Scte35Xml => {
+                    let mut w = xml::Writer::new(0);
+                    xml::splice_info_section(&mut w, &header, &command, &descriptors);
+                    w.finish()
+                }
+                OutputFormat::Dump => unreachable!(),
+            };
+            for line in tags.lines() {
+                let _ = writeln!(out, "{}{}", label, line);
+            }
+            print!("{}", out);
+            return;
+        }
+        out.push_str(&label);
         if let Some(elementary_pid) = self.elementary_pid {
-            print!("{:?} ", elementary_pid);
+            let _ = write!(out, "{:?} ", elementary_pid);
+        }
+        if let Some((first, last)) = span {
+            if first.index == last.index {
+                let _ = write!(out, "packet {} (offset {}) ", first.index, first.offset);
+            } else {
+                let _ = write!(
+                    out,
+                    "packets {}-{} (offset {}-{}) ",
+                    first.index, last.index, first.offset, last.offset
+                );
+            }
+            if let Some(segment) = self.segment_position(first) {
+                out.push_str(&segment);
+            }
         }
         if let Some(pcr) = self.last_pcr.as_ref().get() {
-            print!("Last {:?}: ", pcr)
+            let _ = write!(out, "Last {:?}: ", pcr);
         }
-        print!("{:?} {:#?}", header, command);
+        let _ = write!(out, "{:?} {:#?}", header, command);
         if let scte35_reader::SpliceCommand::SpliceInsert {
             splice_detail:
                 scte35_reader::SpliceInsert::Insert {
@@ -39,35 +192,172 @@ impl scte35_reader::SpliceInfoProcessor for DumpSpliceInfoProcessor {
             if let Some(pcr) = self.last_pcr.as_ref().get() {
                 let mut diff = time_ref.base() as i64 - pcr.base() as i64;
                 if diff < 0 {
-                    diff += (std::u64::MAX / 2) as i64;
+                    diff += (u64::MAX / 2) as i64;
                 }
-                print!(" {}ms after most recent PCR", diff / 90);
+                let _ = write!(out, " {}ms after most recent PCR", diff / 90);
             }
         }
-        println!();
+        out.push('\n');
         for d in &descriptors {
-            println!(" - {:#?}", d);
+            let _ = writeln!(out, " - {:#?}", d);
+        }
+        print!("{}", out);
+    }
+}
+
+/// Checks the `continuity_counter` of successive packets on a single PID
+#[derive(Default)]
+pub struct ContinuityCheck {
+    last: Option<packet::ContinuityCounter>,
+    duplicate_seen: bool,
+}
+impl ContinuityCheck {
+    /// Returns the expected `continuity_counter` value if the given packet does not follow on
+    /// from the previous one.  A single repeat of the previous packet is permitted, and the
+    /// adaptation field's `discontinuity_indicator` resets the check.
+    fn check(&mut self, pk: &packet::Packet<'_>) -> Option<packet::ContinuityCounter> {
+        let this = pk.continuity_counter();
+        if !pk.adaptation_control().has_payload() {
+            // counter does not increment for packets without payload
+            return None;
+        }
+        let discontinuity_indicator = pk
+            .adaptation_field()
+            .map(|af| af.discontinuity_indicator())
+            .unwrap_or(false);
+        let result = match self.last {
+            Some(last) if !discontinuity_indicator => {
+                if this.follows(last) {
+                    self.duplicate_seen = false;
+                    None
+                } else if this == last && !self.duplicate_seen {
+                    self.duplicate_seen = true;
+                    None
+                } else {
+                    self.duplicate_seen = false;
+                    Some(packet::ContinuityCounter::new((last.count() + 1) & 0b1111))
+                }
+            }
+            _ => None,
+        };
+        self.last = Some(this);
+        result
+    }
+}
+
+/// Works out which TS packets carried each section on a PID, on the basis that a section can only
+/// start in a packet with `payload_unit_start_indicator` set
+struct SpanTracker {
+    start: Option<PacketPosition>,
+    span: SectionSpan,
+}
+impl SpanTracker {
+    fn new(span: SectionSpan) -> Self {
+        SpanTracker { start: None, span }
+    }
+    /// to be called before the packet is passed to the section parser
+    fn before(&mut self, ctx: &DumpDemuxContext, pk: &packet::Packet<'_>) {
+        let here = ctx.position();
+        let start = match self.start {
+            // a non-zero pointer_field means the tail of a section started in an earlier packet
+            // precedes the start of the next section in this packet
+            Some(start) if !pk.payload_unit_start_indicator() || pointer_field(pk) > 0 => start,
+            _ => here,
+        };
+        self.span.set(Some((start, here)));
+    }
+    /// to be called after the packet has been passed to the section parser
+    fn after(&mut self, ctx: &DumpDemuxContext, pk: &packet::Packet<'_>) {
+        if pk.payload_unit_start_indicator() {
+            self.start = Some(ctx.position());
+        }
+        self.span.set(None);
+    }
+}
+
+fn pointer_field(pk: &packet::Packet<'_>) -> u8 {
+    pk.payload().and_then(|p| p.first().copied()).unwrap_or(0)
+}
+
+/// Keeps a copy of each section passed on to the wrapped parser, for output formats which
+/// include the original section data
+pub struct CaptureSection<P> {
+    raw: Rc<cell::RefCell<Vec<u8>>>,
+    inner: P,
+}
+impl<P> CaptureSection<P> {
+    pub fn new(raw: Rc<cell::RefCell<Vec<u8>>>, inner: P) -> Self {
+        CaptureSection { raw, inner }
+    }
+}
+impl<P: psi::WholeCompactSyntaxPayloadParser> psi::WholeCompactSyntaxPayloadParser
+    for CaptureSection<P>
+{
+    type Context = P::Context;
+
+    fn section(&mut self, ctx: &mut Self::Context, header: &psi::SectionCommonHeader, data: &[u8]) {
+        {
+            let mut raw = self.raw.borrow_mut();
+            raw.clear();
+            raw.extend_from_slice(data);
         }
+        self.inner.section(ctx, header, data);
+    }
+}
+
+/// Parses SCTE-35 sections, passing them to a `DumpSpliceInfoProcessor`
+pub type Scte35Parser = CaptureSection<
+    scte35_reader::Scte35SectionProcessor<DumpSpliceInfoProcessor, DumpDemuxContext>,
+>;
+impl Scte35Parser {
+    pub fn from_processor(processor: DumpSpliceInfoProcessor) -> Self {
+        CaptureSection::new(
+            processor.raw.clone(),
+            scte35_reader::Scte35SectionProcessor::new(processor),
+        )
+    }
+}
+
+/// Decodes a single `splice_info_section()` given directly, rather than found within a transport
+/// stream
+pub fn dump_section(data: &[u8], processor: DumpSpliceInfoProcessor) -> Result<(), String> {
+    if data.len() < psi::SectionCommonHeader::SIZE {
+        return Err(format!("section data too short: {} bytes", data.len()));
     }
+    let cues = processor.cues.clone();
+    let mut parser = Scte35Parser::from_processor(processor);
+    let header = psi::SectionCommonHeader::new(&data[..psi::SectionCommonHeader::SIZE]);
+    let mut ctx = DumpDemuxContext::new();
+    psi::WholeCompactSyntaxPayloadParser::section(&mut parser, &mut ctx, &header, data);
+    if cues.get() == 0 {
+        return Err("not a valid splice_info_section".to_string());
+    }
+    Ok(())
 }
 
 pub struct Scte35StreamConsumer {
+    pid: Pid,
+    spans: SpanTracker,
+    continuity: ContinuityCheck,
+    /// the `DumpDemuxContext::timeline` that `continuity` is checking within
+    timeline: u64,
+    discontinuity: Rc<cell::Cell<bool>>,
     section: psi::SectionPacketConsumer<
-        psi::CompactSyntaxSectionProcessor<
-            psi::BufferCompactSyntaxParser<
-                scte35_reader::Scte35SectionProcessor<DumpSpliceInfoProcessor, DumpDemuxContext>,
-            >,
-        >,
+        psi::CompactSyntaxSectionProcessor<psi::BufferCompactSyntaxParser<Scte35Parser>>,
     >,
 }
 
 impl Scte35StreamConsumer {
-    fn new(elementary_pid: Pid, last_pcr: Rc<cell::Cell<Option<packet::ClockRef>>>) -> Self {
-        let parser = scte35_reader::Scte35SectionProcessor::new(DumpSpliceInfoProcessor {
-            elementary_pid: Some(elementary_pid),
-            last_pcr,
-        });
+    fn new(elementary_pid: Pid, processor: DumpSpliceInfoProcessor) -> Self {
+        let spans = SpanTracker::new(processor.span.clone());
+        let discontinuity = processor.discontinuity.clone();
+        let parser = Scte35Parser::from_processor(processor);
         Scte35StreamConsumer {
+            pid: elementary_pid,
+            spans,
+            continuity: ContinuityCheck::default(),
+            timeline: 0,
+            discontinuity,
             section: psi::SectionPacketConsumer::new(psi::CompactSyntaxSectionProcessor::new(
                 psi::BufferCompactSyntaxParser::new(parser),
             )),
@@ -75,24 +365,29 @@ impl Scte35StreamConsumer {
     }
 
     fn construct(
-        last_pcr: Rc<cell::Cell<Option<packet::ClockRef>>>,
+        ctx: &mut DumpDemuxContext,
         program_pid: packet::Pid,
         pmt: &psi::pmt::PmtSection<'_>,
         stream_info: &psi::pmt::StreamInfo<'_>,
     ) -> DumpFilterSwitch {
         if scte35_reader::is_scte35(pmt) {
             println!(
-                "Program {:?}: Found SCTE-35 data on {:?} ({:#x})",
+                "{}Program {:?}: Found SCTE-35 data on {:?} ({:#x})",
+                ctx.prefix(),
                 program_pid,
                 stream_info.elementary_pid(),
                 u16::from(stream_info.elementary_pid())
             );
-            DumpFilterSwitch::Scte35(Scte35StreamConsumer::new(
-                stream_info.elementary_pid(),
-                last_pcr,
-            ))
+            let pid = stream_info.elementary_pid();
+            let processor = DumpSpliceInfoProcessor {
+                last_pcr: ctx.last_pcr(program_pid),
+                discontinuity: ctx.monitor(pid, "SCTE-35"),
+                ..ctx.splice_info_processor(pid)
+            };
+            DumpFilterSwitch::Scte35(Scte35StreamConsumer::new(pid, processor))
         } else {
-            println!("Program {:?}: {:?} has type {:?}, but PMT lacks 'CUEI' registration_descriptor that would indicate SCTE-35 content",
+            println!("{}Program {:?}: {:?} has type {:?}, but PMT lacks 'CUEI' registration_descriptor that would indicate SCTE-35 content",
+                     ctx.prefix(),
                      program_pid,
                      stream_info.elementary_pid(),
                      stream_info.stream_type());
@@ -103,48 +398,618 @@ impl Scte35StreamConsumer {
 impl demultiplex::PacketFilter for Scte35StreamConsumer {
     type Ctx = DumpDemuxContext;
     fn consume(&mut self, ctx: &mut Self::Ctx, pk: &packet::Packet<'_>) {
+        if self.timeline != ctx.timeline {
+            self.timeline = ctx.timeline;
+            self.continuity = ContinuityCheck::default();
+        }
+        if let Some(expected) = self.continuity.check(pk) {
+            ctx.report_discontinuity(self.pid, "SCTE-35", expected, pk.continuity_counter());
+            self.discontinuity.set(true);
+        }
+        self.spans.before(ctx, pk);
+        self.section.consume(ctx, pk);
+        self.spans.after(ctx, pk);
+    }
+}
+
+/// Section parser used by `--scan` mode, which is attached to PIDs regardless of what PSI says
+/// about them.  Only sections with the SCTE-35 `table_id` and a valid CRC are passed on to be
+/// decoded, so that PES payloads and other tables on the PID are quietly ignored.
+pub struct ScanSectionParser {
+    pid: Pid,
+    inner: Scte35Parser,
+}
+impl psi::WholeCompactSyntaxPayloadParser for ScanSectionParser {
+    type Context = DumpDemuxContext;
+
+    fn section(&mut self, ctx: &mut Self::Context, header: &psi::SectionCommonHeader, data: &[u8]) {
+        if header.table_id != SPLICE_INFO_TABLE_ID {
+            return;
+        }
+        if mpeg2ts_reader::mpegts_crc::sum32(data) != 0 {
+            return;
+        }
+        let prefix = ctx.prefix();
+        if let Some(ref mut scan) = ctx.scan {
+            scan.found(&prefix, self.pid);
+        }
+        self.inner.section(ctx, header, data);
+    }
+}
+
+/// Filter installed on otherwise unhandled PIDs in `--scan` mode, and alongside `PcrWatch` on
+/// PIDs that the PMT gives a stream type other than SCTE-35
+pub struct ScanFilter {
+    spans: SpanTracker,
+    section: psi::SectionPacketConsumer<
+        psi::CompactSyntaxSectionProcessor<psi::BufferCompactSyntaxParser<ScanSectionParser>>,
+    >,
+}
+impl ScanFilter {
+    fn new(pid: Pid, processor: DumpSpliceInfoProcessor) -> Self {
+        let spans = SpanTracker::new(processor.span.clone());
+        let inner = Scte35Parser::from_processor(processor);
+        ScanFilter {
+            spans,
+            section: psi::SectionPacketConsumer::new(psi::CompactSyntaxSectionProcessor::new(
+                psi::BufferCompactSyntaxParser::new(ScanSectionParser { pid, inner }),
+            )),
+        }
+    }
+}
+impl demultiplex::PacketFilter for ScanFilter {
+    type Ctx = DumpDemuxContext;
+    fn consume(&mut self, ctx: &mut Self::Ctx, pk: &packet::Packet<'_>) {
+        self.spans.before(ctx, pk);
         self.section.consume(ctx, pk);
+        self.spans.after(ctx, pk);
+    }
+}
+
+/// Tally of the SCTE-35 sections discovered by `--scan` mode on each PID
+#[derive(Default)]
+pub struct ScanResults {
+    sections: HashMap<Pid, usize>,
+}
+impl ScanResults {
+    fn found(&mut self, prefix: &str, pid: Pid) {
+        let count = self.sections.entry(pid).or_insert(0);
+        if *count == 0 {
+            println!(
+                "{}Scan: found SCTE-35 splice_info_section on {:?} ({:#x})",
+                prefix,
+                pid,
+                u16::from(pid)
+            );
+        }
+        *count += 1;
+    }
+
+    pub fn print_summary(&self, label: Option<&str>) {
+        let label = label_prefix(label);
+        let mut out = String::new();
+        if self.sections.is_empty() {
+            let _ = writeln!(
+                out,
+                "{}Scan: no SCTE-35 sections found outside of PMT-announced SCTE-35 streams",
+                label
+            );
+        } else {
+            let mut pids: Vec<_> = self.sections.iter().collect();
+            pids.sort_by_key(|(pid, _)| u16::from(**pid));
+            let _ = writeln!(
+                out,
+                "{}Scan: SCTE-35 sections found outside of PMT-announced SCTE-35 streams:",
+                label
+            );
+            for (pid, count) in pids {
+                let _ = writeln!(
+                    out,
+                    " - {:?} ({:#x}): {} sections",
+                    pid,
+                    u16::from(*pid),
+                    count
+                );
+            }
+        }
+        print!("{}", out);
     }
 }
 
-pub struct PcrWatch(Rc<cell::Cell<Option<packet::ClockRef>>>);
+/// Formats a label identifying the input that a line of output relates to, e.g. the source file
+/// when several are being processed
+fn label_prefix(label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("[{}] ", label),
+        None => String::new(),
+    }
+}
+
+/// Details of a stream announced in a program's PMT, retained so that we can tell what changed
+/// when a new version of the PMT arrives
+#[derive(PartialEq)]
+struct AnnouncedStream {
+    stream_type: mpeg2ts_reader::StreamType,
+    scte35: bool,
+}
+
+/// Replacement for the PMT handling built in to `mpeg2ts_reader`, which tracks the PMT
+/// `version_number` so that changes to the program's streams can be reported.  Filters for streams
+/// that are unchanged by a new PMT version are left in place, so that any partially received
+/// SCTE-35 section on those PIDs is not lost.
+struct PmtProcessor {
+    pid: Pid,
+    program_number: u16,
+    version: Option<u8>,
+    pcr_pid: Option<Pid>,
+    streams: HashMap<Pid, AnnouncedStream>,
+}
+impl PmtProcessor {
+    fn new_table(
+        &mut self,
+        ctx: &mut DumpDemuxContext,
+        version: u8,
+        sect: &psi::pmt::PmtSection<'_>,
+    ) {
+        if let Some(last) = self.version {
+            println!(
+                "{}Program {:?}: PMT version changed from {} to {}",
+                ctx.prefix(),
+                self.pid,
+                last,
+                version
+            );
+        }
+        let pcr_pid = sect.pcr_pid();
+        let old_pcr_pid = self.pcr_pid.replace(pcr_pid);
+        let pcr_moved = old_pcr_pid.is_some_and(|old| old != pcr_pid);
+        if let (true, Some(old)) = (pcr_moved, old_pcr_pid) {
+            println!(
+                "{}Program {:?}: PCR_PID changed from {:?} to {:?}",
+                ctx.prefix(),
+                self.pid,
+                old,
+                pcr_pid
+            );
+            // the PCR checks are rebuilt below for the new PID, if it carries a stream
+            ctx.monitored.remove(&old);
+        }
+        let cuei = scte35_reader::is_scte35(sect);
+        let mut seen = HashMap::new();
+        for stream_info in sect.streams() {
+            let pid = stream_info.elementary_pid();
+            let stream = AnnouncedStream {
+                stream_type: stream_info.stream_type(),
+                scte35: cuei && stream_info.stream_type() == scte35_reader::SCTE35_STREAM_TYPE,
+            };
+            let old = self.streams.remove(&pid);
+            // the filters for the PIDs gaining or losing the PCR are replaced even when their
+            // streams are otherwise unchanged, so that the PCR checks move with the PCR
+            let pcr_changed = pcr_moved && (pid == pcr_pid || old_pcr_pid == Some(pid));
+            if old.as_ref() != Some(&stream) || pcr_changed {
+                if let Some(ref old) = old {
+                    if old.stream_type != stream.stream_type {
+                        println!(
+                            "{}Program {:?}: {:?} stream type changed from {:?} to {:?}",
+                            ctx.prefix(),
+                            self.pid,
+                            pid,
+                            old.stream_type,
+                            stream.stream_type
+                        );
+                    }
+                    if old.scte35 && !stream.scte35 {
+                        println!(
+                            "{}Program {:?}: SCTE-35 PID removed {:?} ({:#x})",
+                            ctx.prefix(),
+                            self.pid,
+                            pid,
+                            u16::from(pid)
+                        );
+                    }
+                }
+                if stream.scte35
+                    && self.version.is_some()
+                    && !old.map(|o| o.scte35).unwrap_or(false)
+                {
+                    println!(
+                        "{}Program {:?}: SCTE-35 PID added {:?} ({:#x})",
+                        ctx.prefix(),
+                        self.pid,
+                        pid,
+                        u16::from(pid)
+                    );
+                }
+                // the replacement filter starts monitoring afresh, for whatever the PID now carries
+                ctx.monitored.remove(&pid);
+                let filter = ctx.construct(demultiplex::FilterRequest::ByStream {
+                    program_pid: self.pid,
+                    stream_type: stream_info.stream_type(),
+                    pmt: sect,
+                    stream_info: &stream_info,
+                });
+                ctx.filter_changeset().insert(pid, filter);
+            }
+            seen.insert(pid, stream);
+        }
+        for (pid, old) in self.streams.drain() {
+            if old.scte35 {
+                println!(
+                    "{}Program {:?}: SCTE-35 PID removed {:?} ({:#x})",
+                    ctx.prefix(),
+                    self.pid,
+                    pid,
+                    u16::from(pid)
+                );
+            } else {
+                println!(
+                    "{}Program {:?}: {:?} with type {:?} removed",
+                    ctx.prefix(),
+                    self.pid,
+                    pid,
+                    old.stream_type
+                );
+            }
+            ctx.filter_changeset().remove(pid);
+            ctx.monitored.remove(&pid);
+        }
+        self.streams = seen;
+        self.version = Some(version);
+    }
+}
+impl psi::WholeSectionSyntaxPayloadParser for PmtProcessor {
+    type Context = DumpDemuxContext;
+
+    fn section<'a>(
+        &mut self,
+        ctx: &mut Self::Context,
+        header: &psi::SectionCommonHeader,
+        table_syntax_header: &psi::TableSyntaxHeader<'a>,
+        data: &'a [u8],
+    ) {
+        if header.table_id != 0x02 {
+            println!(
+                "{}Program {:?}: expected PMT table_id 0x2, but got {:#x}",
+                ctx.prefix(),
+                self.pid,
+                header.table_id
+            );
+            return;
+        }
+        let start = psi::SectionCommonHeader::SIZE + psi::TableSyntaxHeader::SIZE;
+        let end = data.len() - 4; // remove CRC bytes
+        match psi::pmt::PmtSection::from_bytes(&data[start..end]) {
+            Ok(sect) => self.new_table(ctx, table_syntax_header.version(), &sect),
+            Err(e) => println!(
+                "{}Program {:?}: problem reading PMT (program_number {}): {:?}",
+                ctx.prefix(),
+                self.pid,
+                self.program_number,
+                e
+            ),
+        }
+    }
+}
+
+pub struct PmtFilter {
+    section: psi::SectionPacketConsumer<
+        psi::SectionSyntaxSectionProcessor<
+            psi::DedupSectionSyntaxPayloadParser<
+                psi::BufferSectionSyntaxParser<
+                    psi::CrcCheckWholeSectionSyntaxPayloadParser<PmtProcessor>,
+                >,
+            >,
+        >,
+    >,
+}
+impl PmtFilter {
+    fn new(pid: Pid, program_number: u16) -> Self {
+        let processor = PmtProcessor {
+            pid,
+            program_number,
+            version: None,
+            pcr_pid: None,
+            streams: HashMap::new(),
+        };
+        PmtFilter {
+            section: psi::SectionPacketConsumer::new(psi::SectionSyntaxSectionProcessor::new(
+                psi::DedupSectionSyntaxPayloadParser::new(psi::BufferSectionSyntaxParser::new(
+                    psi::CrcCheckWholeSectionSyntaxPayloadParser::new(processor),
+                )),
+            )),
+        }
+    }
+}
+impl demultiplex::PacketFilter for PmtFilter {
+    type Ctx = DumpDemuxContext;
+    fn consume(&mut self, ctx: &mut Self::Ctx, pk: &packet::Packet<'_>) {
+        self.section.consume(ctx, pk);
+    }
+}
+
+/// Checks applied to the program's `PCR_PID`
+struct PcrPidChecks {
+    pid: Pid,
+    continuity: ContinuityCheck,
+    analysis: crate::pcr::PcrAnalysis,
+}
+
+pub struct PcrWatch {
+    last_pcr: Rc<cell::Cell<Option<packet::ClockRef>>>,
+    /// only present for the program's `PCR_PID`
+    checks: Option<PcrPidChecks>,
+    /// the `DumpDemuxContext::timeline` that `checks` are being made within
+    timeline: u64,
+    /// in `--scan` mode, looks for SCTE-35 sent with the wrong stream type
+    scan: Option<ScanFilter>,
+}
 impl demultiplex::PacketFilter for PcrWatch {
     type Ctx = DumpDemuxContext;
-    fn consume(&mut self, _ctx: &mut Self::Ctx, pk: &packet::Packet<'_>) {
+    fn consume(&mut self, ctx: &mut Self::Ctx, pk: &packet::Packet<'_>) {
+        if let Some(ref mut checks) = self.checks {
+            if self.timeline != ctx.timeline {
+                self.timeline = ctx.timeline;
+                checks.continuity = ContinuityCheck::default();
+                checks.analysis.reset();
+            }
+            if let Some(expected) = checks.continuity.check(pk) {
+                ctx.report_discontinuity(checks.pid, "PCR", expected, pk.continuity_counter());
+            }
+        }
         if let Some(af) = pk.adaptation_field() {
             if let Ok(pcr) = af.pcr() {
-                self.0.set(Some(pcr));
+                if let Some(ref mut checks) = self.checks {
+                    checks.analysis.pcr(
+                        checks.pid,
+                        ctx.packet_index,
+                        pcr,
+                        af.discontinuity_indicator(),
+                        ctx.receive_time,
+                    );
+                }
+                self.last_pcr.set(Some(pcr));
+                ctx.pcr(pcr);
             }
         }
+        if let Some(ref mut scan) = self.scan {
+            scan.consume(ctx, pk);
+        }
+    }
+}
+
+/// A PID whose packets we particularly care about losing
+struct Monitored {
+    kind: &'static str,
+    discontinuity: Rc<cell::Cell<bool>>,
+}
+
+/// Pass the given TS data to the demultiplexer one packet at a time, so that the context can keep
+/// track of the index of the current packet.  Packets with `transport_error_indicator` set are
+/// reported here, since the demultiplexer will discard them without passing them to any filter.
+pub fn push(
+    demux: &mut demultiplex::Demultiplex<DumpDemuxContext>,
+    ctx: &mut DumpDemuxContext,
+    buf: &[u8],
+) {
+    for data in buf.chunks_exact(packet::Packet::SIZE) {
+        if ctx.is_done() {
+            break;
+        }
+        if let Some(pk) = packet::Packet::try_new(data) {
+            if pk.transport_error_indicator() {
+                ctx.transport_error(pk.pid());
+            }
+        }
+        demux.push(ctx, data);
+        ctx.packet_index += 1;
+        ctx.offset += packet::Packet::SIZE as u64;
     }
 }
 
 mpeg2ts_reader::packet_filter_switch! {
     DumpFilterSwitch<DumpDemuxContext> {
         Pat: demultiplex::PatPacketFilter<DumpDemuxContext>,
-        Pmt: demultiplex::PmtPacketFilter<DumpDemuxContext>,
+        Pmt: PmtFilter,
         Null: demultiplex::NullPacketFilter<DumpDemuxContext>,
         Scte35: Scte35StreamConsumer,
         Pcr: PcrWatch,
+        Scan: ScanFilter,
     }
 }
 pub struct DumpDemuxContext {
     changeset: demultiplex::FilterChangeset<DumpFilterSwitch>,
     last_pcrs: HashMap<packet::Pid, Rc<cell::Cell<Option<packet::ClockRef>>>>,
+    scan: Option<ScanResults>,
+    monitored: HashMap<packet::Pid, Monitored>,
+    packet_index: u64,
+    offset: u64,
+    receive_time: Option<Instant>,
+    cues: Rc<cell::Cell<u64>>,
+    max_cues: Option<u64>,
+    end_offset: Option<u64>,
+    label: Option<Rc<str>>,
+    segments: Segments,
+    format: OutputFormat,
+    timescale: u64,
+    cue_out: Rc<cell::RefCell<hls::CueOut>>,
+    /// set when a new segment has started, but no PCR has yet been seen within it
+    segment_pending: bool,
+    /// incremented at each discontinuity signalled outside of the TS packets themselves, across
+    /// which continuity counters and PCRs need not follow on
+    timeline: u64,
 }
 impl DumpDemuxContext {
     pub fn new() -> Self {
         DumpDemuxContext {
             changeset: demultiplex::FilterChangeset::default(),
             last_pcrs: HashMap::new(),
+            scan: None,
+            monitored: HashMap::new(),
+            packet_index: 0,
+            offset: 0,
+            receive_time: None,
+            cues: Rc::new(cell::Cell::new(0)),
+            max_cues: None,
+            end_offset: None,
+            label: None,
+            segments: Default::default(),
+            format: OutputFormat::Dump,
+            timescale: 90_000,
+            cue_out: Default::default(),
+            segment_pending: false,
+            timeline: 0,
+        }
+    }
+    /// Creates a context which will look for SCTE-35 sections on every PID not otherwise
+    /// handled, rather than relying only on PAT/PMT to identify them
+    pub fn new_scanning() -> Self {
+        DumpDemuxContext {
+            scan: Some(ScanResults::default()),
+            ..Self::new()
+        }
+    }
+    /// Record the time at which the data next passed to `push()` was received from the network
+    pub fn set_receive_time(&mut self, time: Instant) {
+        self.receive_time = Some(time);
+    }
+    /// Create a processor for the sections found on the given PID, sharing the state this context
+    /// uses to keep track of cues
+    fn splice_info_processor(&self, pid: packet::Pid) -> DumpSpliceInfoProcessor {
+        DumpSpliceInfoProcessor {
+            cues: self.cues.clone(),
+            label: self.label.clone(),
+            segments: self.segments.clone(),
+            format: self.format,
+            timescale: self.timescale,
+            cue_out: self.cue_out.clone(),
+            ..DumpSpliceInfoProcessor::new(Some(pid))
+        }
+    }
+
+    /// Label each cue record with the given text, to identify the input it came from
+    pub fn set_label(&mut self, label: &str) {
+        self.label = Some(label.into());
+    }
+    /// Text to place before each line of output, identifying the input it relates to
+    pub fn prefix(&self) -> String {
+        label_prefix(self.label.as_deref())
+    }
+    /// Note that the next packet passed to `push()` is the first of the given segment
+    pub fn start_segment(&mut self, uri: &str) {
+        let start = self.position();
+        self.segments.borrow_mut().push(Segment {
+            uri: uri.into(),
+            start,
+        });
+        self.segment_pending = self.format == OutputFormat::HlsCue;
+    }
+    /// Note that the next packet passed to `push()` follows a discontinuity (an HLS
+    /// `EXT-X-DISCONTINUITY`), so that continuity and PCR checks start afresh rather than
+    /// reporting errors across it
+    pub fn discontinuity(&mut self) {
+        self.timeline += 1;
+    }
+    /// Called for each PCR, so that segment-level HLS tags can be produced using the time at
+    /// which a new segment starts
+    fn pcr(&mut self, pcr: packet::ClockRef) {
+        if self.segment_pending {
+            self.segment_pending = false;
+            if let Some(tag) = self.cue_out.borrow_mut().segment_start(pcr.base()) {
+                print!("{}{}", self.prefix(), tag);
+            }
         }
     }
+    /// Render cues in the given format, with times in units of `1/timescale` seconds where the
+    /// format calls for it
+    pub fn set_format(&mut self, format: OutputFormat, timescale: u64) {
+        self.format = format;
+        self.timescale = timescale;
+    }
+    /// The number of cues found so far
+    pub fn cue_count(&self) -> u64 {
+        self.cues.get()
+    }
+
+    /// Set the position of the next packet to be passed to `push()`, for when input does not
+    /// start at the beginning of a file
+    pub fn set_position(&mut self, pos: PacketPosition) {
+        self.packet_index = pos.index;
+        self.offset = pos.offset;
+    }
+    /// Stop processing once the given number of cues have been found
+    pub fn set_max_cues(&mut self, max_cues: u64) {
+        self.max_cues = Some(max_cues);
+    }
+    /// Stop processing upon reaching the packet at the given byte offset
+    pub fn set_end_offset(&mut self, offset: u64) {
+        self.end_offset = Some(offset);
+    }
+    /// `true` once any limit set by `set_max_cues()` or `set_end_offset()` has been reached
+    pub fn is_done(&self) -> bool {
+        self.max_cues.is_some_and(|max| self.cues.get() >= max)
+            || self.end_offset.is_some_and(|end| self.offset >= end)
+    }
+
+    /// The position of the packet currently being processed
+    pub fn position(&self) -> PacketPosition {
+        PacketPosition {
+            index: self.packet_index,
+            offset: self.offset,
+        }
+    }
+    pub fn scan_results(&self) -> Option<&ScanResults> {
+        self.scan.as_ref()
+    }
     pub fn last_pcr(&self, program_pid: packet::Pid) -> Rc<cell::Cell<Option<packet::ClockRef>>> {
         self.last_pcrs
             .get(&program_pid)
             .expect("last_pcrs entry didn't exist on call to last_pcr()")
             .clone()
     }
+
+    /// Start watching for lost packets on the given PID, returning a flag that will be set when
+    /// a packet is dropped due to `transport_error_indicator`
+    fn monitor(&mut self, pid: packet::Pid, kind: &'static str) -> Rc<cell::Cell<bool>> {
+        self.monitored
+            .entry(pid)
+            .or_insert_with(|| Monitored {
+                kind,
+                discontinuity: Rc::new(cell::Cell::new(false)),
+            })
+            .discontinuity
+            .clone()
+    }
+
+    fn transport_error(&mut self, pid: packet::Pid) {
+        if let Some(monitored) = self.monitored.get(&pid) {
+            println!(
+                "{}{:?} ({}): transport_error_indicator set at packet {}, packet discarded",
+                self.prefix(),
+                pid,
+                monitored.kind,
+                self.packet_index
+            );
+            monitored.discontinuity.set(true);
+        }
+    }
+
+    fn report_discontinuity(
+        &self,
+        pid: packet::Pid,
+        kind: &str,
+        expected: packet::ContinuityCounter,
+        actual: packet::ContinuityCounter,
+    ) {
+        println!(
+            "{}{:?} ({}): continuity_counter discontinuity at packet {}: expected {}, got {}",
+            self.prefix(),
+            pid,
+            kind,
+            self.packet_index,
+            expected.count(),
+            actual.count()
+        );
+    }
 }
 impl demultiplex::DemuxContext for DumpDemuxContext {
     type F = DumpFilterSwitch;
@@ -158,6 +1023,11 @@ impl demultiplex::DemuxContext for DumpDemuxContext {
             demultiplex::FilterRequest::ByPid(packet::Pid::PAT) => {
                 DumpFilterSwitch::Pat(demultiplex::PatPacketFilter::default())
             }
+            demultiplex::FilterRequest::ByPid(pid)
+                if self.scan.is_some() && pid != mpeg2ts_reader::STUFFING_PID =>
+            {
+                DumpFilterSwitch::Scan(ScanFilter::new(pid, self.splice_info_processor(pid)))
+            }
             demultiplex::FilterRequest::ByPid(_) => {
                 DumpFilterSwitch::Null(demultiplex::NullPacketFilter::default())
             }
@@ -166,22 +1036,60 @@ impl demultiplex::DemuxContext for DumpDemuxContext {
                 stream_type: scte35_reader::SCTE35_STREAM_TYPE,
                 pmt,
                 stream_info,
-            } => Scte35StreamConsumer::construct(
-                self.last_pcr(program_pid),
+            } => {
+                let filter = Scte35StreamConsumer::construct(self, program_pid, pmt, stream_info);
+                match filter {
+                    // the PMT didn't convince us this is SCTE-35, but look anyway when scanning
+                    DumpFilterSwitch::Null(_) if self.scan.is_some() => {
+                        let pid = stream_info.elementary_pid();
+                        DumpFilterSwitch::Scan(ScanFilter::new(
+                            pid,
+                            self.splice_info_processor(pid),
+                        ))
+                    }
+                    f => f,
+                }
+            }
+            demultiplex::FilterRequest::ByStream {
                 program_pid,
                 pmt,
                 stream_info,
-            ),
-            demultiplex::FilterRequest::ByStream { program_pid, .. } => {
-                DumpFilterSwitch::Pcr(PcrWatch(self.last_pcr(program_pid)))
+                ..
+            } => {
+                let pid = stream_info.elementary_pid();
+                let checks = if pmt.pcr_pid() == pid {
+                    self.monitor(pid, "PCR");
+                    Some(PcrPidChecks {
+                        pid,
+                        continuity: ContinuityCheck::default(),
+                        analysis: crate::pcr::PcrAnalysis::new(self.prefix()),
+                    })
+                } else {
+                    None
+                };
+                // a PMT announcing SCTE-35 as, say, private data (0x06) shouldn't stop the scan
+                let scan = if self.scan.is_some() {
+                    Some(ScanFilter::new(pid, self.splice_info_processor(pid)))
+                } else {
+                    None
+                };
+                DumpFilterSwitch::Pcr(PcrWatch {
+                    last_pcr: self.last_pcr(program_pid),
+                    checks,
+                    timeline: self.timeline,
+                    scan,
+                })
             }
             demultiplex::FilterRequest::Pmt {
                 pid,
                 program_number,
             } => {
-                // prepare structure needed to print PCR values later on
-                self.last_pcrs.insert(pid, Rc::new(cell::Cell::new(None)));
-                DumpFilterSwitch::Pmt(demultiplex::PmtPacketFilter::new(pid, program_number))
+                // prepare structure needed to print PCR values later on, retaining any existing
+                // state if the PAT is re-announcing a program we already know about
+                self.last_pcrs
+                    .entry(pid)
+                    .or_insert_with(|| Rc::new(cell::Cell::new(None)));
+                DumpFilterSwitch::Pmt(PmtFilter::new(pid, program_number))
             }
             demultiplex::FilterRequest::Nit { .. } => {
                 DumpFilterSwitch::Null(demultiplex::NullPacketFilter::default())
@@ -1,6 +1,10 @@
+#[cfg(target_os = "linux")]
+use crate::batch;
 use crate::cli;
 use crate::mpegts;
+use crate::rtp;
 use mpeg2ts_reader::demultiplex;
+use net2::UdpSocketExt;
 use smpte2022_1_fec::heap_pool::HeapPacket;
 use smpte2022_1_fec::heap_pool::HeapPool;
 use smpte2022_1_fec::BufferPool;
@@ -8,68 +12,135 @@ use smpte2022_1_fec::Decoder;
 use smpte2022_1_fec::Packet;
 use smpte2022_1_fec::PacketStatus;
 use smpte2022_1_fec::Receiver;
+use std::collections::HashSet;
 use std::io;
 use std::net;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Size of the buffers used for packets when FEC decoding
+pub const PACKET_SIZE_MAX: usize = 1500;
+/// Number of buffers needed to hold a full FEC matrix and its column and row packets, with some
+/// to spare
+pub const PACKET_COUNT_MAX: usize = 10 * 10 * 2 + 4 + 25;
+
+/// How often the kernel's count of dropped datagrams is checked and reported
+const DROP_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 
 pub fn main(cmd: &cli::NetCmd) {
     let sock = create_socket(cmd, cmd.addr.port()).expect("Failed to create socket");
-    if cmd.udpts {
-        udpts_main(sock)
+    if let Some(ref path_b) = cmd.path_b {
+        merge_main(sock, path_b, cmd).unwrap()
+    } else if cmd.udpts {
+        udpts_main(sock, cmd)
     } else {
         match cmd.fec {
-            cli::Fec::None => simple_main(sock),
+            cli::Fec::None => simple_main(sock, cmd),
             cli::Fec::ProMpeg => fec_main(sock, cmd).unwrap(),
         }
     }
 }
 
-fn udpts_main(sock: std::net::UdpSocket) {
-    let mut buf = vec![0; 9000];
+pub fn new_context(cmd: &cli::NetCmd) -> mpegts::DumpDemuxContext {
     let mut ctx = mpegts::DumpDemuxContext::new();
+    ctx.set_format(cmd.format, cmd.timescale);
+    ctx
+}
+
+fn udpts_main(sock: std::net::UdpSocket, cmd: &cli::NetCmd) {
+    let mut ctx = new_context(cmd);
     let mut demux = demultiplex::Demultiplex::new(&mut ctx);
+    receive(&sock, cmd, |data, arrival| {
+        ctx.set_receive_time(arrival);
+        mpegts::push(&mut demux, &mut ctx, data)
+    });
+}
+
+/// Simple loop that blocks in recv_from() or recvmmsg() (which minimises the number of syscalls vs.
+/// something that also does select/epoll/etc in addition to receiving).
+fn simple_main(sock: std::net::UdpSocket, cmd: &cli::NetCmd) {
+    let mut recv = RtpReceiver::new(new_context(cmd), cmd);
+    receive(&sock, cmd, |data, arrival| recv.packet(data, arrival));
+}
+
+/// Passes each datagram arriving on the socket to `handle` with its arrival time, until an error
+/// occurs, receiving up to `cmd.batch` datagrams per system call
+#[cfg(target_os = "linux")]
+fn receive(sock: &net::UdpSocket, cmd: &cli::NetCmd, mut handle: impl FnMut(&[u8], Instant)) {
+    let mut filter = SenderFilter::new(cmd.sender, String::new());
+    if cmd.batch <= 1 {
+        return receive_each(sock, filter, handle);
+    }
+    if cmd.rcvbuf.is_none() {
+        if let Err(e) = set_rcvbuf(sock, batch::RCVBUF_SIZE) {
+            println!("UDP: could not set receive buffer size: {}", e);
+        }
+    }
+    let mut recv = match batch::BatchReceiver::new(sock, cmd.batch) {
+        Ok(r) => r,
+        Err(e) => {
+            println!(
+                "UDP: kernel drop counts unavailable ({}), receiving datagrams one at a time",
+                e
+            );
+            return receive_each(sock, filter, handle);
+        }
+    };
+    let mut last_check = Instant::now();
     loop {
-        match sock.recv_from(&mut buf[..]) {
-            Ok((size, _addr)) => demux.push(&mut ctx, &buf[..size]),
+        match recv.recv(sock) {
+            Ok(_) => (),
+            // e.g. a signal delivered to a debugger or profiler attached to the process
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
             Err(e) => {
-                println!("recv_from() error: {:?}", e);
+                println!("recvmmsg() error: {:?}", e);
                 return;
             }
         }
+        // reported no more often than DropMonitor would, so that sustained overflow doesn't
+        // flood the output, which would only make matters worse
+        let elapsed = last_check.elapsed();
+        if elapsed >= DROP_CHECK_INTERVAL {
+            last_check += elapsed;
+            if let Some((new, total)) = recv.new_drops() {
+                println!(
+                    "UDP: kernel dropped {} datagrams in the last {:.1}s (receive buffer overflow), {} in total",
+                    new,
+                    elapsed.as_secs_f64(),
+                    total
+                );
+            }
+        }
+        for (data, from, arrival) in recv.datagrams() {
+            if filter.accept(from) {
+                handle(data, arrival);
+            }
+        }
     }
 }
+#[cfg(not(target_os = "linux"))]
+fn receive(sock: &net::UdpSocket, cmd: &cli::NetCmd, handle: impl FnMut(&[u8], Instant)) {
+    receive_each(sock, SenderFilter::new(cmd.sender, String::new()), handle)
+}
 
-/// Simple loop that blocks in recv_from() (which minimises the number of syscalls vs. something
-/// that also does select/epoll/etc in addition to calling recv_from().
-fn simple_main(sock: std::net::UdpSocket) {
+fn receive_each(
+    sock: &net::UdpSocket,
+    mut filter: SenderFilter,
+    mut handle: impl FnMut(&[u8], Instant),
+) {
     let mut buf = vec![0; 9000];
-    let mut ctx = mpegts::DumpDemuxContext::new();
-    let mut demux = demultiplex::Demultiplex::new(&mut ctx);
-    let mut expected = None;
+    let mut drops = DropMonitor::new(sock);
     loop {
         match sock.recv_from(&mut buf[..]) {
-            Ok((size, addr)) => {
-                let rtp = rtp_rs::RtpReader::new(&buf[..size]);
-                match rtp {
-                    Ok(rtp) => {
-                        let this_seq = rtp.sequence_number();
-                        if let Some(seq) = expected {
-                            if this_seq != seq {
-                                println!(
-                                    "RTP: sequence mismatch: expected {:?}, got {:?}",
-                                    seq,
-                                    rtp.sequence_number()
-                                );
-                            }
-                        }
-                        expected = Some(this_seq.next());
-                        //println!("got a packet from {:?}, seq {:?}", addr, rtp.sequence_number());
-                        demux.push(&mut ctx, rtp.payload());
-                    }
-                    Err(e) => {
-                        println!("rtp error from {:?}: {:?}", addr, e);
-                    }
+            Ok((size, from)) => {
+                if filter.accept(from) {
+                    handle(&buf[..size], Instant::now());
+                }
+                if let Some(ref mut drops) = drops {
+                    drops.check("");
                 }
             }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
             Err(e) => {
                 println!("recv_from() error: {:?}", e);
                 return;
@@ -78,38 +149,251 @@ fn simple_main(sock: std::net::UdpSocket) {
     }
 }
 
-struct ScteFecReceiver {
-    ctx: mpegts::DumpDemuxContext,
-    demux: demultiplex::Demultiplex<mpegts::DumpDemuxContext>,
-    expected_seq: Option<rtp_rs::Seq>,
+/// Most other senders reported by `SenderFilter`, so that a flood of them can't use up memory
+const IGNORED_SENDERS_MAX: usize = 64;
+
+/// Passes only the datagrams from the sender given with `--sender`, if any, reporting the first
+/// datagram from each other sender
+pub struct SenderFilter {
+    sender: Option<cli::Sender>,
+    ignored: HashSet<net::SocketAddr>,
+    prefix: String,
 }
-impl Receiver<HeapPacket> for ScteFecReceiver {
-    fn receive(&mut self, packets: impl Iterator<Item = (HeapPacket, PacketStatus)>) {
-        for (pk, _pk_status) in packets {
-            let rtp = rtp_rs::RtpReader::new(pk.payload());
-            match rtp {
-                Ok(rtp) => {
-                    let this_seq = rtp.sequence_number();
-                    if let Some(seq) = self.expected_seq {
-                        if this_seq != seq {
-                            println!(
-                                "RTP: sequence mismatch: expected {:?}, got {:?}",
-                                seq,
-                                rtp.sequence_number()
-                            );
+impl SenderFilter {
+    pub fn new(sender: Option<cli::Sender>, prefix: String) -> SenderFilter {
+        SenderFilter {
+            sender,
+            ignored: HashSet::new(),
+            prefix,
+        }
+    }
+
+    pub fn accept(&mut self, from: net::SocketAddr) -> bool {
+        let Some(sender) = self.sender else {
+            return true;
+        };
+        // IPv4 senders appear as IPv4-mapped IPv6 addresses to sockets bound to ::
+        if from.ip().to_canonical() == sender.addr.to_canonical()
+            && sender.port.is_none_or(|port| port == from.port())
+        {
+            return true;
+        }
+        if self.ignored.len() < IGNORED_SENDERS_MAX && self.ignored.insert(from) {
+            println!(
+                "{}UDP: ignoring datagrams from {}, which is not the chosen sender",
+                self.prefix, from
+            );
+        }
+        false
+    }
+}
+
+/// Reports datagrams that the kernel has dropped for a socket because its receive buffer was full,
+/// from the `drops` column of `/proc/net/udp`, for sockets not read with `recvmmsg()` (which has
+/// the count delivered along with the datagrams)
+pub struct DropMonitor {
+    port: u16,
+    ipv6: bool,
+    inode: u64,
+    drops: u64,
+    last_check: Instant,
+}
+impl DropMonitor {
+    /// Gives `None` if the count is unavailable, as on platforms other than Linux
+    pub fn new(sock: &net::UdpSocket) -> Option<DropMonitor> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let addr = sock.local_addr().ok()?;
+        let inode = socket_inode(sock)?;
+        let mut mon = DropMonitor {
+            port: addr.port(),
+            ipv6: addr.is_ipv6(),
+            inode,
+            drops: 0,
+            last_check: Instant::now(),
+        };
+        mon.drops = mon.read()?;
+        Some(mon)
+    }
+
+    /// Reports any increase in the count, at most once per `DROP_CHECK_INTERVAL`
+    pub fn check(&mut self, prefix: &str) {
+        let now = Instant::now();
+        let elapsed = now - self.last_check;
+        if elapsed < DROP_CHECK_INTERVAL {
+            return;
+        }
+        self.last_check = now;
+        let Some(drops) = self.read() else {
+            return;
+        };
+        if drops > self.drops {
+            println!(
+                "{}UDP: port {}: kernel dropped {} datagrams in the last {:.1}s (receive buffer overflow), {} in total",
+                prefix,
+                self.port,
+                drops - self.drops,
+                elapsed.as_secs_f64(),
+                drops
+            );
+        }
+        self.drops = drops;
+    }
+
+    fn read(&self) -> Option<u64> {
+        let path = if self.ipv6 {
+            "/proc/net/udp6"
+        } else {
+            "/proc/net/udp"
+        };
+        let table = std::fs::read_to_string(path).ok()?;
+        table.lines().skip(1).find_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if fields.get(9)?.parse::<u64>().ok()? == self.inode {
+                fields.last()?.parse().ok()
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// The inode number identifying the socket in `/proc/net/udp`
+#[cfg(unix)]
+fn socket_inode(sock: &net::UdpSocket) -> Option<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let link = std::fs::read_link(format!("/proc/self/fd/{}", sock.as_raw_fd())).ok()?;
+    link.to_str()?
+        .strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+#[cfg(not(unix))]
+fn socket_inode(_sock: &net::UdpSocket) -> Option<u64> {
+    None
+}
+
+/// Receives the two copies of a SMPTE ST 2022-7 protected stream, from `sock` and from the second
+/// path, merging them into one stream for the demultiplexer
+fn merge_main(
+    sock: std::net::UdpSocket,
+    path_b: &cli::Path,
+    cmd: &cli::NetCmd,
+) -> Result<(), io::Error> {
+    let sock_b = create_path_socket(cmd, path_b).expect("Failed to create socket for path B");
+    let mut drops: Vec<_> = [&sock, &sock_b]
+        .iter()
+        .filter_map(|s| DropMonitor::new(s))
+        .collect();
+    let mut sockets = vec![];
+    for sock in [sock, sock_b] {
+        sock.set_nonblocking(true)?;
+        sockets.push(mio::net::UdpSocket::from_std(sock));
+    }
+    let mut poll = mio::Poll::new()?;
+    for (i, sock) in sockets.iter_mut().enumerate() {
+        poll.registry()
+            .register(sock, mio::Token(i), mio::Interest::READABLE)?;
+    }
+
+    let mut merger = rtp::Merger::new(cmd.ssrc);
+    let mut recv = RtpReceiver::new(new_context(cmd), cmd);
+    let mut buf = vec![0; 9000];
+    let mut events = mio::Events::with_capacity(1024);
+    loop {
+        poll.poll(&mut events, None)?;
+        for event in &events {
+            let path = event.token().0;
+            loop {
+                let size = match sockets[path].recv(&mut buf) {
+                    Ok(size) => size,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                };
+                let data = &buf[..size];
+                match rtp_rs::RtpReader::new(data) {
+                    Ok(rtp) => {
+                        if merger.packet(path, &rtp, &recv.prefix) {
+                            recv.packet(data, Instant::now());
                         }
                     }
-                    self.expected_seq = Some(this_seq.next());
-                    //println!("got a packet from {:?}, seq {:?}", addr, rtp.sequence_number());
-                    self.demux.push(&mut self.ctx, rtp.payload());
+                    Err(e) => println!("{}rtp error: {:?}", recv.prefix, e),
                 }
-                Err(e) => {
-                    println!("rtp error: {:?}", e);
+            }
+        }
+        merger.report(&recv.prefix);
+        for d in &mut drops {
+            d.check(&recv.prefix);
+        }
+    }
+}
+
+/// Restores the order of RTP packets using their sequence numbers, keeps statistics on the
+/// stream, and passes the packets' payloads to the demultiplexer
+pub struct RtpReceiver {
+    ctx: mpegts::DumpDemuxContext,
+    demux: demultiplex::Demultiplex<mpegts::DumpDemuxContext>,
+    reorder: rtp::ReorderBuffer,
+    stats: rtp::RtpStats,
+    prefix: String,
+}
+impl RtpReceiver {
+    pub fn new(mut ctx: mpegts::DumpDemuxContext, cmd: &cli::NetCmd) -> RtpReceiver {
+        let demux = demultiplex::Demultiplex::new(&mut ctx);
+        RtpReceiver {
+            prefix: ctx.prefix(),
+            ctx,
+            demux,
+            reorder: rtp::ReorderBuffer::new(cmd.reorder_window, cmd.path_b.is_some()),
+            stats: rtp::RtpStats::new(cmd.ssrc, cmd.path_b.is_some()),
+        }
+    }
+
+    /// Handles a packet received from the network at the given time
+    pub fn packet(&mut self, data: &[u8], arrival: Instant) {
+        self.rtp_packet(data, Some(arrival));
+    }
+
+    /// Handles a packet, given its arrival time unless it was recovered by FEC
+    fn rtp_packet(&mut self, data: &[u8], arrival: Option<Instant>) {
+        match rtp_rs::RtpReader::new(data) {
+            Ok(rtp) => {
+                let now = arrival.unwrap_or_else(Instant::now);
+                let ctx = &mut self.ctx;
+                let demux = &mut self.demux;
+                let mut deliver = |payload: &[u8]| {
+                    ctx.set_receive_time(now);
+                    mpegts::push(demux, ctx, payload);
+                };
+                self.stats.report(self.reorder.counts(), &self.prefix);
+                match self.stats.packet(&rtp, arrival, &self.prefix) {
+                    rtp::Source::Same => (),
+                    rtp::Source::Switched => self.reorder.reset(&self.prefix, &mut deliver),
+                    rtp::Source::Ignored => return,
                 }
+                self.reorder
+                    .push(rtp.sequence_number(), rtp.payload(), &self.prefix, deliver);
+            }
+            Err(e) => {
+                println!("{}rtp error: {:?}", self.prefix, e);
             }
         }
     }
 }
+impl Receiver<HeapPacket> for RtpReceiver {
+    fn receive(&mut self, packets: impl Iterator<Item = (HeapPacket, PacketStatus)>) {
+        for (pk, pk_status) in packets {
+            let arrival = match pk_status {
+                PacketStatus::Received => Some(Instant::now()),
+                PacketStatus::Recovered => None,
+            };
+            self.rtp_packet(pk.payload(), arrival);
+        }
+    }
+}
 
 /// Supports FEC decoding, which means needing to read from multiple sockets, which can't really
 /// be done with blocking as in simple_main()
@@ -118,27 +402,21 @@ fn fec_main(main_sock: std::net::UdpSocket, cmd: &cli::NetCmd) -> Result<(), std
     const FEC_ONE: mio::Token = mio::Token(1);
     const FEC_TWO: mio::Token = mio::Token(2);
 
-    const PACKET_SIZE_MAX: usize = 1500;
-    const PACKET_COUNT_MAX: usize = 10 * 10 * 2 + 4 + 25;
-
+    let fec_one = create_socket(cmd, cmd.addr.port() + 2).expect("Failed to create FEC socket");
+    let fec_two = create_socket(cmd, cmd.addr.port() + 4).expect("Failed to create FEC socket");
+    let mut drops: Vec<_> = [&main_sock, &fec_one, &fec_two]
+        .iter()
+        .filter_map(|s| DropMonitor::new(s))
+        .collect();
     main_sock.set_nonblocking(true).expect("set_nonblocking");
     let mut main_sock = mio::net::UdpSocket::from_std(main_sock);
-    let fec_one = create_socket(cmd, cmd.addr.port() + 2).expect("Failed to create FEC socket");
     fec_one.set_nonblocking(true).expect("set_nonblocking");
     let mut fec_one = mio::net::UdpSocket::from_std(fec_one);
-    let fec_two = create_socket(cmd, cmd.addr.port() + 4).expect("Failed to create FEC socket");
     fec_two.set_nonblocking(true).expect("set_nonblocking");
     let mut fec_two = mio::net::UdpSocket::from_std(fec_two);
 
     let buffer_pool = HeapPool::new(PACKET_COUNT_MAX, PACKET_SIZE_MAX);
-    let mut ctx = mpegts::DumpDemuxContext::new();
-    let demux = demultiplex::Demultiplex::new(&mut ctx);
-    let recv = ScteFecReceiver {
-        ctx,
-        demux,
-        expected_seq: None,
-    };
-    let mut decoder = Decoder::new(buffer_pool.clone(), recv);
+    let mut decoder = Decoder::new(buffer_pool.clone(), RtpReceiver::new(new_context(cmd), cmd));
 
     let mut poll = mio::Poll::new()?;
     poll.registry()
@@ -148,6 +426,9 @@ fn fec_main(main_sock: std::net::UdpSocket, cmd: &cli::NetCmd) -> Result<(), std
     poll.registry()
         .register(&mut fec_two, FEC_TWO, mio::Interest::READABLE)?;
 
+    let mut filter = SenderFilter::new(cmd.sender, String::new());
+    // FEC packets are sent from ports of their own
+    let mut fec_filter = SenderFilter::new(cmd.sender.map(cli::Sender::any_port), String::new());
     let mut events = mio::Events::with_capacity(1024);
     loop {
         poll.poll(&mut events, None)?;
@@ -155,8 +436,9 @@ fn fec_main(main_sock: std::net::UdpSocket, cmd: &cli::NetCmd) -> Result<(), std
             match event.token() {
                 MAIN => loop {
                     let mut pk = buffer_pool.allocate().expect("allocating main buffer");
-                    let size = match main_sock.recv(pk.payload_mut()) {
-                        Ok(s) => s,
+                    let size = match main_sock.recv_from(pk.payload_mut()) {
+                        Ok((_, from)) if !filter.accept(from) => continue,
+                        Ok((s, _)) => s,
                         Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                             break;
                         }
@@ -169,8 +451,9 @@ fn fec_main(main_sock: std::net::UdpSocket, cmd: &cli::NetCmd) -> Result<(), std
                 },
                 FEC_ONE => loop {
                     let mut pk = buffer_pool.allocate().expect("allocating fec1 buffer");
-                    let size = match fec_one.recv(pk.payload_mut()) {
-                        Ok(s) => s,
+                    let size = match fec_one.recv_from(pk.payload_mut()) {
+                        Ok((_, from)) if !fec_filter.accept(from) => continue,
+                        Ok((s, _)) => s,
                         Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                             break;
                         }
@@ -183,8 +466,9 @@ fn fec_main(main_sock: std::net::UdpSocket, cmd: &cli::NetCmd) -> Result<(), std
                 },
                 FEC_TWO => loop {
                     let mut pk = buffer_pool.allocate().expect("allocating fec2 buffer");
-                    let size = match fec_two.recv(pk.payload_mut()) {
-                        Ok(s) => s,
+                    let size = match fec_two.recv_from(pk.payload_mut()) {
+                        Ok((_, from)) if !fec_filter.accept(from) => continue,
+                        Ok((s, _)) => s,
                         Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                             break;
                         }
@@ -198,17 +482,182 @@ fn fec_main(main_sock: std::net::UdpSocket, cmd: &cli::NetCmd) -> Result<(), std
                 t => panic!("unexpected {:?}", t),
             }
         }
+        for d in &mut drops {
+            d.check("");
+        }
     }
 }
 
-fn create_socket(cmd: &cli::NetCmd, port: u16) -> Result<std::net::UdpSocket, io::Error> {
-    let udp = net2::UdpBuilder::new_v4()?;
+pub fn create_socket(cmd: &cli::NetCmd, port: u16) -> Result<std::net::UdpSocket, io::Error> {
+    bind_socket(cmd, cmd.addr.ip(), cmd.group.as_ref(), port)
+}
+
+/// Creates the socket for the second path of a SMPTE ST 2022-7 protected stream
+fn create_path_socket(
+    cmd: &cli::NetCmd,
+    path: &cli::Path,
+) -> Result<std::net::UdpSocket, io::Error> {
+    bind_socket(cmd, path.addr.ip(), path.group.as_ref(), path.addr.port())
+}
+
+fn bind_socket(
+    cmd: &cli::NetCmd,
+    ip: net::IpAddr,
+    group: Option<&cli::Group>,
+    port: u16,
+) -> Result<std::net::UdpSocket, io::Error> {
+    let udp = if ip.is_ipv4() {
+        net2::UdpBuilder::new_v4()?
+    } else {
+        net2::UdpBuilder::new_v6()?
+    };
     udp.reuse_address(true)?; // TODO: only if mcast?
 
-    let addr = net::SocketAddr::new(cmd.addr.ip(), port);
+    let addr = net::SocketAddr::new(ip, port);
     let sock = udp.bind(addr)?;
-    if let Some(ref group) = cmd.group {
-        sock.join_multicast_v4(&group.addr, &group.ifaddr)?;
+    if let Some(size) = cmd.rcvbuf {
+        let granted = set_rcvbuf(&sock, size)?;
+        if granted < size {
+            println!(
+                "UDP: port {}: receive buffer limited to {} of the {} bytes requested (raise the net.core.rmem_max sysctl)",
+                port, granted, size
+            );
+        }
+    }
+    if let Some(group) = group {
+        if group.sources.is_empty() {
+            match group.addr {
+                net::IpAddr::V4(addr) => sock.join_multicast_v4(&addr, &group.ifaddr)?,
+                net::IpAddr::V6(addr) => sock.join_multicast_v6(&addr, group.ifindex)?,
+            }
+        }
+        for source in &group.sources {
+            join_source_specific(&sock, group, *source)?;
+        }
     }
     Ok(sock)
 }
+
+/// Asks for a socket receive buffer of the given size, returning the size actually granted
+fn set_rcvbuf(sock: &net::UdpSocket, size: usize) -> Result<usize, io::Error> {
+    sock.set_recv_buffer_size(size)?;
+    let granted = rcvbuf(sock)?;
+    #[cfg(target_os = "linux")]
+    if granted < size {
+        // beyond the net.core.rmem_max limit, which is allowed if we have CAP_NET_ADMIN
+        let size = size.min(libc::c_int::MAX as usize) as libc::c_int;
+        if setsockopt(sock, libc::SOL_SOCKET, libc::SO_RCVBUFFORCE, &size).is_ok() {
+            return rcvbuf(sock);
+        }
+    }
+    Ok(granted)
+}
+
+fn rcvbuf(sock: &net::UdpSocket) -> Result<usize, io::Error> {
+    let size = sock.recv_buffer_size()?;
+    // Linux reports double the size that was asked for, the extra allowing for its bookkeeping
+    Ok(if cfg!(target_os = "linux") {
+        size / 2
+    } else {
+        size
+    })
+}
+
+/// Joins the group for traffic from the given sender only (an IGMPv3 / MLDv2 source-specific
+/// join), which neither `std` nor `net2` provide
+#[cfg(target_os = "linux")]
+fn join_source_specific(
+    sock: &net::UdpSocket,
+    group: &cli::Group,
+    source: net::IpAddr,
+) -> Result<(), io::Error> {
+    /// `struct group_source_req` from `<netinet/in.h>`, which the libc crate lacks
+    #[repr(C)]
+    struct GroupSourceReq {
+        gsr_interface: u32,
+        gsr_group: libc::sockaddr_storage,
+        gsr_source: libc::sockaddr_storage,
+    }
+
+    fn in_addr(addr: net::Ipv4Addr) -> libc::in_addr {
+        libc::in_addr {
+            s_addr: u32::from_ne_bytes(addr.octets()),
+        }
+    }
+    fn sockaddr_in6(addr: net::Ipv6Addr) -> libc::sockaddr_storage {
+        // safety: all-zero is a valid sockaddr_storage, which is large enough for sockaddr_in6
+        let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let sin6 = &mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6;
+        unsafe {
+            (*sin6).sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            (*sin6).sin6_addr.s6_addr = addr.octets();
+        }
+        storage
+    }
+
+    match (group.addr, source) {
+        (net::IpAddr::V4(addr), net::IpAddr::V4(source)) => {
+            let req = libc::ip_mreq_source {
+                imr_multiaddr: in_addr(addr),
+                imr_interface: in_addr(group.ifaddr),
+                imr_sourceaddr: in_addr(source),
+            };
+            setsockopt(sock, libc::IPPROTO_IP, libc::IP_ADD_SOURCE_MEMBERSHIP, &req)
+        }
+        (net::IpAddr::V6(addr), net::IpAddr::V6(source)) => {
+            let req = GroupSourceReq {
+                gsr_interface: group.ifindex,
+                gsr_group: sockaddr_in6(addr),
+                gsr_source: sockaddr_in6(source),
+            };
+            setsockopt(
+                sock,
+                libc::IPPROTO_IPV6,
+                libc::MCAST_JOIN_SOURCE_GROUP,
+                &req,
+            )
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "source and group addresses must be of the same IP version",
+        )),
+    }
+}
+#[cfg(not(target_os = "linux"))]
+fn join_source_specific(
+    _sock: &net::UdpSocket,
+    _group: &cli::Group,
+    _source: net::IpAddr,
+) -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "source-specific multicast is not supported on this platform",
+    ))
+}
+
+/// Sets a socket option for which `std` and `net2` have no method
+#[cfg(target_os = "linux")]
+fn setsockopt<T>(
+    sock: &net::UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: &T,
+) -> Result<(), io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    // safety: value points to a properly initialised option value of the given size
+    let res = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,154 @@
+//! Checks on the timing of the Program Clock Reference values found on a program's `PCR_PID`, so
+//! that problems with cue timing can be correlated with problems in the program clock.
+
+use mpeg2ts_reader::packet;
+use std::time::{Duration, Instant};
+
+/// PCR values are 42-bit, 27MHz quantities which wrap around after 2^33 ticks of the 90kHz base
+const PCR_MODULUS: u64 = (1 << 33) * 300;
+const PCR_TICKS_PER_MS: u64 = 27_000;
+
+/// ISO/IEC 13818-1 requires that PCRs are no more than 100ms apart
+const MAX_INTERVAL_MS: u64 = 100;
+/// ETSI TR 101 290 recommends PCRs at least every 40ms
+const RECOMMENDED_INTERVAL_MS: u64 = 40;
+/// A forward jump larger than this is assumed to be an unsignalled discontinuity, rather than
+/// just a late PCR
+const DISCONTINUITY_MS: u64 = 1000;
+
+/// How often to report on PCR accuracy when the receive time of packets is known
+const ACCURACY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tracks the difference between elapsed PCR time and elapsed receive time over a reporting
+/// interval
+struct Accuracy {
+    start_pcr: u64,
+    start_time: Instant,
+    min_offset_ms: f64,
+    max_offset_ms: f64,
+}
+impl Accuracy {
+    fn new(pcr: u64, time: Instant) -> Accuracy {
+        Accuracy {
+            start_pcr: pcr,
+            start_time: time,
+            min_offset_ms: 0.0,
+            max_offset_ms: 0.0,
+        }
+    }
+}
+
+pub struct PcrAnalysis {
+    /// placed before each line of output, to identify the input being analysed
+    prefix: String,
+    last: Option<packet::ClockRef>,
+    accuracy: Option<Accuracy>,
+}
+impl PcrAnalysis {
+    pub fn new(prefix: String) -> PcrAnalysis {
+        PcrAnalysis {
+            prefix,
+            last: None,
+            accuracy: None,
+        }
+    }
+
+    /// Forget the previous PCR, for when the clock is expected to restart, so that the next is
+    /// not reported as a discontinuity
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.accuracy = None;
+    }
+
+    /// Check the given PCR value against the previous one, printing details of any problem
+    /// found.  `received` is the time the containing packet arrived, if known (i.e. for network
+    /// input).
+    pub fn pcr(
+        &mut self,
+        pid: packet::Pid,
+        packet_index: u64,
+        pcr: packet::ClockRef,
+        discontinuity_indicator: bool,
+        received: Option<Instant>,
+    ) {
+        let this = u64::from(pcr);
+        if let Some(last) = self.last {
+            let diff = (this + PCR_MODULUS - u64::from(last)) % PCR_MODULUS;
+            if discontinuity_indicator {
+                println!(
+                    "{}{:?} (PCR): discontinuity signalled at packet {}: {:?} -> {:?}",
+                    self.prefix, pid, packet_index, last, pcr
+                );
+                self.accuracy = None;
+            } else if diff > PCR_MODULUS / 2 {
+                println!(
+                    "{}{:?} (PCR): went backwards by {}ms at packet {}: {:?} -> {:?}",
+                    self.prefix,
+                    pid,
+                    (PCR_MODULUS - diff) / PCR_TICKS_PER_MS,
+                    packet_index,
+                    last,
+                    pcr
+                );
+                self.accuracy = None;
+            } else if diff > DISCONTINUITY_MS * PCR_TICKS_PER_MS {
+                println!(
+                    "{}{:?} (PCR): unsignalled discontinuity of {}ms at packet {}: {:?} -> {:?}",
+                    self.prefix,
+                    pid,
+                    diff / PCR_TICKS_PER_MS,
+                    packet_index,
+                    last,
+                    pcr
+                );
+                self.accuracy = None;
+            } else if diff > MAX_INTERVAL_MS * PCR_TICKS_PER_MS {
+                println!(
+                    "{}{:?} (PCR): interval of {}ms at packet {} exceeds the {}ms limit",
+                    self.prefix,
+                    pid,
+                    diff / PCR_TICKS_PER_MS,
+                    packet_index,
+                    MAX_INTERVAL_MS
+                );
+            } else if diff > RECOMMENDED_INTERVAL_MS * PCR_TICKS_PER_MS {
+                println!(
+                    "{}{:?} (PCR): interval of {}ms at packet {} exceeds the recommended {}ms",
+                    self.prefix,
+                    pid,
+                    diff / PCR_TICKS_PER_MS,
+                    packet_index,
+                    RECOMMENDED_INTERVAL_MS
+                );
+            }
+        }
+        self.last = Some(pcr);
+        if let Some(received) = received {
+            self.accuracy(pid, this, received);
+        }
+    }
+
+    fn accuracy(&mut self, pid: packet::Pid, pcr: u64, received: Instant) {
+        let acc = self
+            .accuracy
+            .get_or_insert_with(|| Accuracy::new(pcr, received));
+        let pcr_elapsed_ms =
+            ((pcr + PCR_MODULUS - acc.start_pcr) % PCR_MODULUS) as f64 / PCR_TICKS_PER_MS as f64;
+        let wall_elapsed = received.saturating_duration_since(acc.start_time);
+        let wall_elapsed_ms = wall_elapsed.as_secs_f64() * 1000.0;
+        let offset_ms = pcr_elapsed_ms - wall_elapsed_ms;
+        acc.min_offset_ms = acc.min_offset_ms.min(offset_ms);
+        acc.max_offset_ms = acc.max_offset_ms.max(offset_ms);
+        if wall_elapsed >= ACCURACY_INTERVAL {
+            println!(
+                "{}{:?} (PCR): drift {:.0}ppm, jitter {:.1}ms against receive time over the last {}s",
+                self.prefix,
+                pid,
+                offset_ms / wall_elapsed_ms * 1_000_000.0,
+                acc.max_offset_ms - acc.min_offset_ms,
+                wall_elapsed.as_secs()
+            );
+            *acc = Accuracy::new(pcr, received);
+        }
+    }
+}
@@ -0,0 +1,1065 @@
+//! Restoring the order of RTP packets which the network has reordered, and telling apart the
+//! packets that were lost, duplicated, or arrived too late to be put back in place
+
+use rtp_rs::RtpReader;
+use rtp_rs::Seq;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A jump forward in sequence numbers of more than this is taken to be the sender restarting,
+/// rather than loss (as `MAX_DROPOUT` in RFC 3550 appendix A.1)
+const MAX_DROPOUT: i32 = 3000;
+/// A jump backwards of more than this is taken to be the sender restarting, and within it the
+/// fate of each packet is remembered, so that duplicates can be told from late arrivals (as
+/// `MAX_MISORDER` in RFC 3550 appendix A.1)
+const MAX_MISORDER: i32 = 100;
+
+/// RTP timestamp clock rate for MPEG-2 transport streams (RFC 2250)
+const CLOCK_RATE: f64 = 90_000.0;
+/// Payload type for MPEG-2 transport streams (RFC 3551)
+const PAYLOAD_TYPE_MP2T: u8 = 33;
+/// A change in the difference between RTP timestamps and arrival times of more than this many
+/// seconds from one packet to the next is reported as a timestamp discontinuity
+const TIMESTAMP_JUMP: f64 = 1.0;
+/// Interval at which RTP statistics are reported
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+/// A sender whose packets reappear within this time of another sender taking over is taken to
+/// be sending at the same time, rather than to have failed over
+const INTERLEAVE_INTERVAL: Duration = Duration::from_secs(1);
+/// How far one path may trail the other when merging, in packets; beyond this the sender is
+/// taken to have restarted
+const MAX_SKEW: i32 = 8192;
+/// Names of the paths being merged, as in SMPTE ST 2022-7
+const PATH_NAMES: [&str; 2] = ["A", "B"];
+/// Most other senders reported as ignored, so that a flood of them can't use up memory
+const IGNORED_SSRCS_MAX: usize = 64;
+
+/// Default for the number of packets that may be held back waiting for a missing one
+pub const DEFAULT_WINDOW: usize = 16;
+/// Upper limit on the window, so that one gap can't hold back more packets than a jump in sequence
+/// numbers that would be taken for the sender restarting
+pub const MAX_WINDOW: usize = MAX_DROPOUT as usize;
+/// Default window when merging two paths, which must also cover the time by which one path
+/// trails the other, so that packets lost on the leading path can be filled from the other
+pub const MERGE_DEFAULT_WINDOW: usize = 256;
+
+/// Running totals of the problems seen in the sequence of packets
+#[derive(Default, Clone, Copy)]
+pub struct SeqCounts {
+    /// packets never received, or received too late to be used
+    pub lost: u64,
+    /// packets received after later ones, but in time to be put back in order
+    pub reordered: u64,
+    /// packets received more than once
+    pub duplicate: u64,
+    /// packets received after the packets following them had already been passed on
+    pub late: u64,
+}
+impl SeqCounts {
+    /// The counts accumulated since the `earlier` totals were taken
+    fn since(&self, earlier: &SeqCounts) -> SeqCounts {
+        SeqCounts {
+            lost: self.lost - earlier.lost,
+            reordered: self.reordered - earlier.reordered,
+            duplicate: self.duplicate - earlier.duplicate,
+            late: self.late - earlier.late,
+        }
+    }
+}
+
+/// Holds back the packets following a gap in the sequence numbers, until either the missing
+/// packet arrives, or `window` packets are waiting, at which point the missing one is given up
+/// as lost
+pub struct ReorderBuffer {
+    window: usize,
+    /// whether the packets come from merging two paths, where those lost on one path are
+    /// routinely filled in from the other (which is reported per path instead), and the copies
+    /// from a trailing path may arrive well behind the others
+    merged: bool,
+    /// the sequence number of the next packet to be passed on, once one has been seen
+    next: Option<Seq>,
+    /// packets waiting on a gap before them, the first entry being for `next`
+    held: VecDeque<Option<Vec<u8>>>,
+    /// whether each of the sequence numbers just before `next` was received, oldest first, going
+    /// back as far as packets are accepted as late rather than as a jump in the sequence
+    history: VecDeque<bool>,
+    counts: SeqCounts,
+}
+impl ReorderBuffer {
+    pub fn new(window: usize, merged: bool) -> ReorderBuffer {
+        ReorderBuffer {
+            window,
+            merged,
+            next: None,
+            held: VecDeque::new(),
+            history: VecDeque::new(),
+            counts: SeqCounts::default(),
+        }
+    }
+
+    pub fn counts(&self) -> SeqCounts {
+        self.counts
+    }
+
+    /// Passes on any held packets and forgets the sequence, for when a different sender takes
+    /// over the stream with sequence numbers of its own
+    pub fn reset(&mut self, prefix: &str, mut deliver: impl FnMut(&[u8])) {
+        let held = self.held.len();
+        self.release(held, prefix, &mut deliver);
+        self.history.clear();
+        self.next = None;
+    }
+
+    /// Accepts the packet with the given sequence number, passing to `deliver` the payloads of
+    /// any packets now ready, in sequence order, and reporting problems with the given prefix
+    pub fn push(&mut self, seq: Seq, payload: &[u8], prefix: &str, mut deliver: impl FnMut(&[u8])) {
+        let Some(next) = self.next else {
+            self.next = Some(seq.next());
+            self.history.push_back(true);
+            deliver(payload);
+            return;
+        };
+        let delta = seq - next;
+        if !(-self.max_misorder()..=MAX_DROPOUT).contains(&delta) {
+            println!(
+                "{}RTP: sequence number jumped from {} to {}, resynchronising",
+                prefix,
+                u16::from(next).wrapping_sub(1),
+                u16::from(seq)
+            );
+            self.reset(prefix, &mut deliver);
+            self.push(seq, payload, prefix, deliver);
+            return;
+        }
+        if delta < 0 {
+            let index = self.history.len() as i32 + delta;
+            let received = if index >= 0 {
+                self.history.get_mut(index as usize)
+            } else {
+                None
+            };
+            match received {
+                Some(received) if *received => {
+                    self.counts.duplicate += 1;
+                    println!("{}RTP: duplicate packet {}", prefix, u16::from(seq));
+                }
+                _ => {
+                    if let Some(received) = received {
+                        *received = true;
+                    }
+                    self.counts.late += 1;
+                    println!(
+                        "{}RTP: packet {} arrived {} packets late, after later packets had been passed on; discarded",
+                        prefix,
+                        u16::from(seq),
+                        -delta
+                    );
+                }
+            }
+            return;
+        }
+        let mut delta = delta as usize;
+        if delta == 0 && self.held.is_empty() {
+            // the usual case, which needs no copying
+            self.advance(true);
+            deliver(payload);
+            return;
+        }
+        if delta > self.window {
+            // give up on the oldest missing packets, to make room within the window
+            let excess = delta - self.window;
+            self.release(excess, prefix, &mut deliver);
+            delta -= excess;
+        }
+        if self.held.len() <= delta {
+            self.held.resize(delta + 1, None);
+        } else if self.held[delta].is_some() {
+            self.counts.duplicate += 1;
+            println!("{}RTP: duplicate packet {}", prefix, u16::from(seq));
+            return;
+        } else {
+            self.counts.reordered += 1;
+            if !self.merged {
+                println!(
+                    "{}RTP: packet {} arrived out of order, after packet {}; reordered",
+                    prefix,
+                    u16::from(seq),
+                    u16::from(seq + (self.held.len() - 1 - delta) as u16)
+                );
+            }
+        }
+        self.held[delta] = Some(payload.to_vec());
+        let ready = self.held.iter().take_while(|p| p.is_some()).count();
+        self.release(ready, prefix, &mut deliver);
+    }
+
+    /// Passes on the first `count` held entries, reporting those which are missing as lost
+    fn release(&mut self, count: usize, prefix: &str, deliver: &mut impl FnMut(&[u8])) {
+        let mut lost: Option<(Seq, u64)> = None;
+        for _ in 0..count {
+            let seq = self.next.expect("packets held before any seen");
+            let entry = self.held.pop_front().flatten();
+            self.advance(entry.is_some());
+            match entry {
+                Some(payload) => {
+                    Self::report_lost(lost.take(), prefix);
+                    deliver(&payload);
+                }
+                None => {
+                    self.counts.lost += 1;
+                    match lost {
+                        Some((_, ref mut n)) => *n += 1,
+                        None => lost = Some((seq, 1)),
+                    }
+                }
+            }
+        }
+        Self::report_lost(lost, prefix);
+    }
+
+    fn report_lost(lost: Option<(Seq, u64)>, prefix: &str) {
+        match lost {
+            Some((seq, 1)) => println!("{}RTP: packet {} lost", prefix, u16::from(seq)),
+            Some((seq, n)) => println!(
+                "{}RTP: {} packets lost, {} to {}",
+                prefix,
+                n,
+                u16::from(seq),
+                u16::from(seq + (n - 1) as u16)
+            ),
+            None => (),
+        }
+    }
+
+    /// Moves on to the next sequence number, recording whether the current one was received
+    fn advance(&mut self, received: bool) {
+        if let Some(ref mut next) = self.next {
+            *next = next.next();
+        }
+        self.history.push_back(received);
+        if self.history.len() > self.max_misorder() as usize {
+            self.history.pop_front();
+        }
+    }
+
+    /// How far behind `next` a packet may be and still be taken as late
+    fn max_misorder(&self) -> i32 {
+        if self.merged {
+            MAX_SKEW
+        } else {
+            MAX_MISORDER
+        }
+    }
+}
+
+/// Whether a packet comes from the sender of the packets before it
+pub enum Source {
+    Same,
+    /// a different sender has taken over the stream
+    Switched,
+    /// the packet is not from the sender being followed, and should be ignored
+    Ignored,
+}
+
+/// The optional parts of an RTP header, reported when they change so that packets from gateways
+/// which add them can be recognised
+#[derive(PartialEq, Clone, Copy, Default)]
+struct HeaderLayout {
+    csrc_count: u8,
+    /// the profile-specific identifier of the header extension, if there is one
+    extension: Option<u16>,
+    padding: bool,
+}
+impl HeaderLayout {
+    fn new(rtp: &RtpReader<'_>) -> HeaderLayout {
+        HeaderLayout {
+            csrc_count: rtp.csrc_count(),
+            extension: rtp.extension().map(|(id, _)| id),
+            padding: rtp.padding().is_some(),
+        }
+    }
+    fn is_plain(&self) -> bool {
+        *self == HeaderLayout::default()
+    }
+}
+impl std::fmt::Display for HeaderLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if self.csrc_count > 0 {
+            parts.push(format!("{} CSRCs", self.csrc_count));
+        }
+        if let Some(id) = self.extension {
+            parts.push(format!("a header extension with identifier {:#06x}", id));
+        }
+        if self.padding {
+            parts.push("padding".to_string());
+        }
+        if parts.is_empty() {
+            f.write_str("no CSRCs, header extension or padding")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// Watches the header fields of the packets of an RTP stream other than the sequence number,
+/// reporting changes of sender, header layout and payload type, and timestamp discontinuities as
+/// they happen, and periodically summarising the stream including its interarrival jitter
+/// (RFC 3550 section 6.4.1)
+pub struct RtpStats {
+    ssrc: Option<u32>,
+    /// whether the packets come from merging two paths, each of which may have a sender with an
+    /// SSRC of its own, so that a different SSRC doesn't mean a different stream
+    merged: bool,
+    /// the only sender to be followed, if given with `--ssrc` or once senders are found to be
+    /// interleaved
+    wanted: Option<u32>,
+    /// the sender replaced by the last change of SSRC, and when that happened
+    displaced: Option<(u32, Instant)>,
+    /// the other senders that have been reported as ignored
+    ignored_ssrcs: HashSet<u32>,
+    layout: HeaderLayout,
+    /// the length of the last payload that was not a whole number of TS packets, until one is
+    bad_payload_len: Option<usize>,
+    payload_type: Option<u8>,
+    /// the RTP timestamp and arrival time of the previous packet received from the network
+    previous: Option<(u32, Instant)>,
+    /// interarrival jitter estimate, in RTP timestamp units
+    jitter: f64,
+    /// the largest jitter estimate within the current interval
+    max_jitter: f64,
+    packets: u64,
+    /// packets from other senders ignored within the current interval
+    ignored: u64,
+    interval_start: Instant,
+    /// sequence problems counted up to the start of the current interval
+    counts: SeqCounts,
+}
+impl RtpStats {
+    /// Follows only the sender with the given SSRC, if there is one
+    pub fn new(ssrc: Option<u32>, merged: bool) -> RtpStats {
+        RtpStats {
+            ssrc: None,
+            merged,
+            wanted: ssrc,
+            displaced: None,
+            ignored_ssrcs: HashSet::new(),
+            layout: HeaderLayout::default(),
+            bad_payload_len: None,
+            payload_type: None,
+            previous: None,
+            jitter: 0.0,
+            max_jitter: 0.0,
+            packets: 0,
+            ignored: 0,
+            interval_start: Instant::now(),
+            counts: SeqCounts::default(),
+        }
+    }
+
+    /// Examines a packet, giving its arrival time unless it was recovered by FEC rather than
+    /// received, and says whether it comes from the same sender as the packets before
+    pub fn packet(
+        &mut self,
+        rtp: &RtpReader<'_>,
+        arrival: Option<Instant>,
+        prefix: &str,
+    ) -> Source {
+        let now = Instant::now();
+        let ssrc = rtp.ssrc();
+        if let Some(wanted) = self.wanted {
+            if ssrc != wanted {
+                self.ignored += 1;
+                if self.ignored_ssrcs.len() < IGNORED_SSRCS_MAX && self.ignored_ssrcs.insert(ssrc) {
+                    println!(
+                        "{}RTP: ignoring packets from SSRC {:#010x}, which is not the chosen sender",
+                        prefix, ssrc
+                    );
+                }
+                return Source::Ignored;
+            }
+        }
+        self.packets += 1;
+        let mut source = Source::Same;
+        match self.ssrc {
+            // the copies of the stream on the two paths are interleaved by design
+            Some(_) if self.merged => (),
+            Some(prev) if prev != ssrc => {
+                match self.displaced {
+                    Some((displaced, when))
+                        if displaced == ssrc && now - when < INTERLEAVE_INTERVAL =>
+                    {
+                        // the sender which was replaced is still sending
+                        println!(
+                            "{}RTP: packets from SSRCs {:#010x} and {:#010x} are interleaved; following {:#010x} only (choose with --ssrc or --sender)",
+                            prefix, ssrc, prev, ssrc
+                        );
+                        self.wanted = Some(ssrc);
+                        self.ignored_ssrcs.insert(prev);
+                    }
+                    _ => println!(
+                        "{}RTP: SSRC changed from {:#010x} to {:#010x}; the sender has switched",
+                        prefix, prev, ssrc
+                    ),
+                }
+                source = Source::Switched;
+                self.displaced = Some((prev, now));
+                self.previous = None;
+                self.jitter = 0.0;
+            }
+            _ => (),
+        }
+        let first = self.ssrc.is_none();
+        self.ssrc = Some(ssrc);
+
+        let layout = HeaderLayout::new(rtp);
+        if first && !layout.is_plain() {
+            println!("{}RTP: packets have {}", prefix, layout);
+        } else if !first && layout != self.layout {
+            println!("{}RTP: packets now have {}", prefix, layout);
+        }
+        self.layout = layout;
+
+        let len = rtp.payload().len();
+        if len.is_multiple_of(mpeg2ts_reader::packet::Packet::SIZE) {
+            self.bad_payload_len = None;
+        } else if self.bad_payload_len != Some(len) {
+            println!(
+                "{}RTP: payload of {} bytes is not a whole number of TS packets",
+                prefix, len
+            );
+            self.bad_payload_len = Some(len);
+        }
+
+        let pt = rtp.payload_type();
+        match self.payload_type {
+            None if pt != PAYLOAD_TYPE_MP2T => println!(
+                "{}RTP: payload type {}, rather than {} (MP2T)",
+                prefix, pt, PAYLOAD_TYPE_MP2T
+            ),
+            Some(prev) if prev != pt => println!(
+                "{}RTP: payload type changed from {} to {}",
+                prefix, prev, pt
+            ),
+            _ => (),
+        }
+        self.payload_type = Some(pt);
+
+        let Some(arrival) = arrival else {
+            return source;
+        };
+        let timestamp = rtp.timestamp();
+        if let Some((prev_timestamp, prev_arrival)) = self.previous {
+            let arrival_delta = (arrival - prev_arrival).as_secs_f64() * CLOCK_RATE;
+            let timestamp_delta = f64::from(timestamp.wrapping_sub(prev_timestamp) as i32);
+            let d = arrival_delta - timestamp_delta;
+            if d.abs() > TIMESTAMP_JUMP * CLOCK_RATE {
+                println!(
+                    "{}RTP: timestamp discontinuity, advancing {:+.3}s over {:.3}s of arrival time",
+                    prefix,
+                    timestamp_delta / CLOCK_RATE,
+                    arrival_delta / CLOCK_RATE
+                );
+            } else {
+                self.jitter += (d.abs() - self.jitter) / 16.0;
+                self.max_jitter = self.max_jitter.max(self.jitter);
+            }
+        }
+        self.previous = Some((timestamp, arrival));
+        source
+    }
+
+    /// Prints a summary of the stream if `STATS_INTERVAL` has passed since the last one, given
+    /// the current totals of sequence problems
+    pub fn report(&mut self, counts: SeqCounts, prefix: &str) {
+        let now = Instant::now();
+        let elapsed = now - self.interval_start;
+        if elapsed < STATS_INTERVAL {
+            return;
+        }
+        for line in self.summary(counts, elapsed) {
+            println!("{}{}", prefix, line);
+        }
+        self.interval_start = now;
+        self.packets = 0;
+        self.ignored = 0;
+        self.max_jitter = self.jitter;
+        self.counts = counts;
+    }
+
+    /// The lines summarising the interval of the given length which is now ending
+    fn summary(&self, counts: SeqCounts, elapsed: Duration) -> Vec<String> {
+        let seq = counts.since(&self.counts);
+        let ms = |ticks: f64| ticks / CLOCK_RATE * 1000.0;
+        let mut lines = vec![format!(
+            "RTP: {} packets in the last {:.1}s, SSRC {:#010x}, payload type {}, jitter {:.2}ms (max {:.2}ms), {} lost, {} reordered, {} duplicate, {} late",
+            self.packets,
+            elapsed.as_secs_f64(),
+            self.ssrc.unwrap_or(0),
+            self.payload_type.unwrap_or(0),
+            ms(self.jitter),
+            ms(self.max_jitter),
+            seq.lost,
+            seq.reordered,
+            seq.duplicate,
+            seq.late
+        )];
+        if self.ignored > 0 {
+            lines.push(format!(
+                "RTP: {} packets from other senders ignored in the last {:.1}s",
+                self.ignored,
+                elapsed.as_secs_f64()
+            ));
+        }
+        lines
+    }
+}
+
+/// Packets lost and received on one of the paths being merged
+#[derive(Default)]
+struct PathCounts {
+    /// the sequence number expected next on this path
+    expected: Option<Seq>,
+    received: u64,
+    /// packets which arrived on this path first, and so were used
+    used: u64,
+    lost: u64,
+    /// packets from senders other than the one chosen with `--ssrc`
+    ignored: u64,
+}
+
+/// Merges two copies of an RTP stream received over separate network paths, per SMPTE ST
+/// 2022-7, by passing on only the first copy of each packet to arrive, so that packets lost on
+/// one path are filled in from the other
+pub struct Merger {
+    /// the SSRC chosen with `--ssrc`, if any, whose packets are the only ones merged
+    ssrc: Option<u32>,
+    /// one bit for each sequence number, set if a copy of the packet has been passed on
+    seen: Vec<u64>,
+    /// the highest sequence number seen on either path
+    highest: Option<Seq>,
+    paths: [PathCounts; 2],
+    interval_start: Instant,
+}
+impl Merger {
+    pub fn new(ssrc: Option<u32>) -> Merger {
+        Merger {
+            ssrc,
+            seen: vec![0; 0x1_0000 / 64],
+            highest: None,
+            paths: [PathCounts::default(), PathCounts::default()],
+            interval_start: Instant::now(),
+        }
+    }
+
+    /// Accepts a packet from path 0 (A) or 1 (B), returning `true` if it is the first copy to
+    /// arrive, to be passed on
+    pub fn packet(&mut self, path: usize, rtp: &RtpReader<'_>, prefix: &str) -> bool {
+        if self.ssrc.is_some_and(|ssrc| ssrc != rtp.ssrc()) {
+            // left out before merging, so that another sender's sequence numbers can't cause
+            // the chosen sender's packets to be discarded as duplicates
+            self.paths[path].ignored += 1;
+            return false;
+        }
+        let seq = rtp.sequence_number();
+        self.path_loss(path, seq, prefix);
+        let counts = &mut self.paths[path];
+        counts.received += 1;
+        match self.highest {
+            Some(highest) => {
+                let delta = seq - highest;
+                if !(-MAX_SKEW..=MAX_DROPOUT).contains(&delta) {
+                    // the sender has restarted, so forget the old sequence numbers, and don't
+                    // count the jump as loss when the other path makes it too
+                    self.seen.iter_mut().for_each(|bits| *bits = 0);
+                    self.highest = Some(seq);
+                    self.paths[1 - path].expected = None;
+                } else if delta > 0 {
+                    // sequence numbers now half the sequence space behind are reused
+                    for s in 1..=delta as u16 {
+                        self.set_seen(highest + s, false);
+                    }
+                    self.highest = Some(seq);
+                }
+            }
+            None => self.highest = Some(seq),
+        }
+        if self.is_seen(seq) {
+            return false;
+        }
+        self.set_seen(seq, true);
+        self.paths[path].used += 1;
+        true
+    }
+
+    /// Notes any gap in the sequence numbers received on one path
+    fn path_loss(&mut self, path: usize, seq: Seq, prefix: &str) {
+        let name = PATH_NAMES[path];
+        let counts = &mut self.paths[path];
+        if let Some(expected) = counts.expected {
+            let delta = seq - expected;
+            if (-MAX_MISORDER..0).contains(&delta) {
+                // reordered within this path, which doesn't move it on
+                return;
+            }
+            if !(0..=MAX_DROPOUT).contains(&delta) {
+                // if the other path kept going, this one was cut off for a while; otherwise
+                // the sender has restarted
+                let outage = self
+                    .highest
+                    .is_some_and(|highest| (-MAX_SKEW..=MAX_DROPOUT).contains(&(seq - highest)));
+                if outage {
+                    // the length of the outage is only known modulo the sequence space
+                    let lost = u16::from(seq).wrapping_sub(u16::from(expected));
+                    counts.lost += u64::from(lost);
+                    println!(
+                        "{}2022-7: path {} resumed after an outage, lost {} packets, {} to {}",
+                        prefix,
+                        name,
+                        lost,
+                        u16::from(expected),
+                        u16::from(seq).wrapping_sub(1)
+                    );
+                } else {
+                    println!(
+                        "{}2022-7: path {} sequence number jumped from {} to {}",
+                        prefix,
+                        name,
+                        u16::from(expected).wrapping_sub(1),
+                        u16::from(seq)
+                    );
+                }
+            } else if delta == 1 {
+                counts.lost += 1;
+                println!(
+                    "{}2022-7: path {} lost packet {}",
+                    prefix,
+                    name,
+                    u16::from(expected)
+                );
+            } else if delta > 1 {
+                counts.lost += delta as u64;
+                println!(
+                    "{}2022-7: path {} lost {} packets, {} to {}",
+                    prefix,
+                    name,
+                    delta,
+                    u16::from(expected),
+                    u16::from(seq).wrapping_sub(1)
+                );
+            }
+        }
+        counts.expected = Some(seq.next());
+    }
+
+    fn is_seen(&self, seq: Seq) -> bool {
+        let s = usize::from(u16::from(seq));
+        self.seen[s / 64] & 1 << (s % 64) != 0
+    }
+
+    fn set_seen(&mut self, seq: Seq, seen: bool) {
+        let s = usize::from(u16::from(seq));
+        if seen {
+            self.seen[s / 64] |= 1 << (s % 64);
+        } else {
+            self.seen[s / 64] &= !(1 << (s % 64));
+        }
+    }
+
+    /// Prints a summary of each path if `STATS_INTERVAL` has passed since the last one
+    pub fn report(&mut self, prefix: &str) {
+        let now = Instant::now();
+        let elapsed = now - self.interval_start;
+        if elapsed < STATS_INTERVAL {
+            return;
+        }
+        let summaries: Vec<_> = self
+            .paths
+            .iter()
+            .zip(PATH_NAMES)
+            .map(|(p, name)| {
+                let mut summary = format!(
+                    "path {}: {} received, {} lost, {} used",
+                    name, p.received, p.lost, p.used
+                );
+                if p.ignored > 0 {
+                    summary.push_str(&format!(", {} from other SSRCs ignored", p.ignored));
+                }
+                summary
+            })
+            .collect();
+        println!(
+            "{}2022-7: in the last {:.1}s, {}",
+            prefix,
+            elapsed.as_secs_f64(),
+            summaries.join("; ")
+        );
+        for p in &mut self.paths {
+            p.received = 0;
+            p.lost = 0;
+            p.used = 0;
+            p.ignored = 0;
+        }
+        self.interval_start = now;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Pushes packets with the given sequence numbers, each carrying its sequence number as its
+    /// payload, returning the sequence numbers of the payloads passed on
+    fn push_all(buf: &mut ReorderBuffer, seqs: &[u16]) -> Vec<u16> {
+        let mut out = vec![];
+        for &seq in seqs {
+            buf.push(Seq::from(seq), &seq.to_be_bytes(), "", |p| {
+                out.push(u16::from_be_bytes([p[0], p[1]]))
+            });
+        }
+        out
+    }
+
+    /// An RTP packet carrying one TS packet
+    fn rtp_packet(seq: u16, timestamp: u32, ssrc: u32, payload_type: u8) -> Vec<u8> {
+        let mut data = vec![0x80, payload_type];
+        data.extend(seq.to_be_bytes());
+        data.extend(timestamp.to_be_bytes());
+        data.extend(ssrc.to_be_bytes());
+        data.extend([0x47, 0x1f, 0xff, 0x10]);
+        data.resize(12 + mpeg2ts_reader::packet::Packet::SIZE, 0xff);
+        data
+    }
+
+    /// Passes a packet to `stats`, as having arrived `ms` milliseconds after `start`
+    fn stats_packet(stats: &mut RtpStats, data: &[u8], start: Instant, ms: u64) -> Source {
+        let rtp = RtpReader::new(data).unwrap();
+        stats.packet(&rtp, Some(start + Duration::from_millis(ms)), "")
+    }
+
+    /// Passes a packet with the given sequence number to `merger` from `path`, returning
+    /// whether it was passed on
+    fn merge(merger: &mut Merger, path: usize, seq: u16, ssrc: u32) -> bool {
+        let data = rtp_packet(seq, u32::from(seq) * 900, ssrc, PAYLOAD_TYPE_MP2T);
+        merger.packet(path, &RtpReader::new(&data).unwrap(), "")
+    }
+
+    fn counts(buf: &ReorderBuffer) -> (u64, u64, u64, u64) {
+        let c = buf.counts();
+        (c.lost, c.reordered, c.duplicate, c.late)
+    }
+
+    #[test]
+    fn in_order() {
+        let mut buf = ReorderBuffer::new(DEFAULT_WINDOW, false);
+        assert_eq!(push_all(&mut buf, &[0, 1, 2, 3]), [0, 1, 2, 3]);
+        assert_eq!(counts(&buf), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn reordered_within_window() {
+        let mut buf = ReorderBuffer::new(4, false);
+        assert_eq!(push_all(&mut buf, &[0, 2, 3]), [0]);
+        assert_eq!(push_all(&mut buf, &[1, 4]), [1, 2, 3, 4]);
+        assert_eq!(counts(&buf), (0, 1, 0, 0));
+    }
+
+    #[test]
+    fn lost_past_window() {
+        let mut buf = ReorderBuffer::new(4, false);
+        assert_eq!(push_all(&mut buf, &[0, 2, 3, 4, 5]), [0]);
+        // a fifth packet waiting means giving up on packet 1
+        assert_eq!(push_all(&mut buf, &[6]), [2, 3, 4, 5, 6]);
+        assert_eq!(counts(&buf), (1, 0, 0, 0));
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut buf = ReorderBuffer::new(4, false);
+        // one already passed on, and one still held behind a gap
+        assert_eq!(push_all(&mut buf, &[0, 1, 1, 3, 3]), [0, 1]);
+        assert_eq!(push_all(&mut buf, &[2]), [2, 3]);
+        assert_eq!(counts(&buf), (0, 1, 2, 0));
+    }
+
+    #[test]
+    fn late() {
+        let mut buf = ReorderBuffer::new(2, false);
+        assert_eq!(push_all(&mut buf, &[0, 2, 3, 4]), [0, 2, 3, 4]);
+        // given up as lost already, so it is discarded, and a second copy is a duplicate
+        assert!(push_all(&mut buf, &[1, 1]).is_empty());
+        assert_eq!(counts(&buf), (1, 0, 1, 1));
+    }
+
+    #[test]
+    fn wraparound() {
+        let mut buf = ReorderBuffer::new(4, false);
+        assert_eq!(push_all(&mut buf, &[65534, 0, 65535]), [65534, 65535, 0]);
+        assert_eq!(push_all(&mut buf, &[2, 1]), [1, 2]);
+        assert_eq!(counts(&buf), (0, 2, 0, 0));
+    }
+
+    #[test]
+    fn zero_window() {
+        let mut buf = ReorderBuffer::new(0, false);
+        assert_eq!(push_all(&mut buf, &[0, 2, 1, 3]), [0, 2, 3]);
+        assert_eq!(counts(&buf), (1, 0, 0, 1));
+    }
+
+    #[test]
+    fn resync_after_jump() {
+        let mut buf = ReorderBuffer::new(4, false);
+        assert_eq!(push_all(&mut buf, &[0, 1, 2]), [0, 1, 2]);
+        // far beyond MAX_DROPOUT, so the sender is taken to have restarted
+        assert_eq!(push_all(&mut buf, &[40000, 40001]), [40000, 40001]);
+        // held packets are passed on when resynchronising, giving up on the gap before them
+        assert_eq!(push_all(&mut buf, &[40003, 20000]), [40003, 20000]);
+        assert_eq!(counts(&buf), (1, 0, 0, 0));
+    }
+
+    #[test]
+    fn summary_counts_per_interval() {
+        let mut buf = ReorderBuffer::new(2, false);
+        let mut stats = RtpStats::new(None, false);
+        let start = Instant::now();
+        for (i, &seq) in [0, 2, 3, 4, 1].iter().enumerate() {
+            let data = rtp_packet(seq, u32::from(seq) * 900, 0x1234, PAYLOAD_TYPE_MP2T);
+            stats_packet(&mut stats, &data, start, i as u64 * 10);
+            push_all(&mut buf, &[seq]);
+        }
+        let summary = stats.summary(buf.counts(), STATS_INTERVAL);
+        assert_eq!(summary.len(), 1);
+        assert!(
+            summary[0].starts_with("RTP: 5 packets in the last 10.0s, SSRC 0x00001234,"),
+            "{}",
+            summary[0]
+        );
+        assert!(
+            summary[0].ends_with("1 lost, 0 reordered, 0 duplicate, 1 late"),
+            "{}",
+            summary[0]
+        );
+
+        // the next interval only counts what happens after this one
+        stats.interval_start -= STATS_INTERVAL;
+        stats.report(buf.counts(), "");
+        push_all(&mut buf, &[6, 5, 5]);
+        let summary = stats.summary(buf.counts(), STATS_INTERVAL);
+        assert!(
+            summary[0].ends_with("0 lost, 1 reordered, 1 duplicate, 0 late"),
+            "{}",
+            summary[0]
+        );
+    }
+
+    #[test]
+    fn jitter() {
+        let mut stats = RtpStats::new(None, false);
+        let start = Instant::now();
+        // 10ms apart by timestamp, but the third packet arrives 5ms late
+        for (seq, ms) in [(0, 0), (1, 10), (2, 25), (3, 30)] {
+            let data = rtp_packet(seq, u32::from(seq) * 900, 0x1234, PAYLOAD_TYPE_MP2T);
+            stats_packet(&mut stats, &data, start, ms);
+        }
+        // J += (|D| - J) / 16 for D of 450 ticks, then of -450 ticks
+        let after_late = 450.0 / 16.0;
+        let expected = after_late + (450.0 - after_late) / 16.0;
+        assert!((stats.jitter - expected).abs() < 0.01, "{}", stats.jitter);
+        assert_eq!(stats.max_jitter, stats.jitter);
+    }
+
+    #[test]
+    fn ssrc_change() {
+        let mut stats = RtpStats::new(None, false);
+        let start = Instant::now();
+        let first = rtp_packet(0, 0, 0x1234, PAYLOAD_TYPE_MP2T);
+        assert!(matches!(
+            stats_packet(&mut stats, &first, start, 0),
+            Source::Same
+        ));
+        let second = rtp_packet(5000, 90000, 0xabcd, PAYLOAD_TYPE_MP2T);
+        assert!(matches!(
+            stats_packet(&mut stats, &second, start, 10),
+            Source::Switched
+        ));
+        assert_eq!(stats.ssrc, Some(0xabcd));
+        // jitter is measured afresh against the new sender's timestamps
+        assert!(stats.previous.is_some_and(|(ts, _)| ts == 90000));
+        assert_eq!(stats.jitter, 0.0);
+    }
+
+    #[test]
+    fn interleaved_senders() {
+        let mut stats = RtpStats::new(None, false);
+        let start = Instant::now();
+        let a = |seq| rtp_packet(seq, 0, 0x1234, PAYLOAD_TYPE_MP2T);
+        let b = |seq| rtp_packet(seq, 0, 0xabcd, PAYLOAD_TYPE_MP2T);
+        stats_packet(&mut stats, &a(0), start, 0);
+        stats_packet(&mut stats, &b(100), start, 1);
+        // the first sender reappearing soon after means both are sending
+        assert!(matches!(
+            stats_packet(&mut stats, &a(1), start, 2),
+            Source::Switched
+        ));
+        assert_eq!(stats.wanted, Some(0x1234));
+        assert!(matches!(
+            stats_packet(&mut stats, &b(101), start, 3),
+            Source::Ignored
+        ));
+        assert!(matches!(
+            stats_packet(&mut stats, &a(2), start, 4),
+            Source::Same
+        ));
+        assert_eq!((stats.packets, stats.ignored), (4, 1));
+    }
+
+    #[test]
+    fn chosen_ssrc() {
+        let mut stats = RtpStats::new(Some(0xabcd), false);
+        let start = Instant::now();
+        let other = rtp_packet(0, 0, 0x1234, PAYLOAD_TYPE_MP2T);
+        assert!(matches!(
+            stats_packet(&mut stats, &other, start, 0),
+            Source::Ignored
+        ));
+        assert_eq!(stats.ssrc, None);
+    }
+
+    #[test]
+    fn unexpected_payload_type() {
+        let mut stats = RtpStats::new(None, false);
+        let start = Instant::now();
+        let data = rtp_packet(0, 0, 0x1234, 96);
+        // reported, but the packet is still used
+        assert!(matches!(
+            stats_packet(&mut stats, &data, start, 0),
+            Source::Same
+        ));
+        assert_eq!(stats.payload_type, Some(96));
+        let data = rtp_packet(1, 900, 0x1234, PAYLOAD_TYPE_MP2T);
+        stats_packet(&mut stats, &data, start, 10);
+        assert_eq!(stats.payload_type, Some(PAYLOAD_TYPE_MP2T));
+        let summary = stats.summary(SeqCounts::default(), STATS_INTERVAL);
+        assert!(summary[0].contains("payload type 33"), "{}", summary[0]);
+    }
+
+    #[test]
+    fn merges_paths() {
+        let mut merger = Merger::new(None);
+        let mut used = vec![];
+        for (path, seq) in [(0, 0), (1, 0), (0, 1), (1, 1), (1, 2), (0, 3), (1, 3)] {
+            if merge(&mut merger, path, seq, 0x1234) {
+                used.push((path, seq));
+            }
+        }
+        // packet 2, lost on path A, is filled in from path B
+        assert_eq!(used, [(0, 0), (0, 1), (1, 2), (0, 3)]);
+        let [a, b] = &merger.paths;
+        assert_eq!((a.received, a.lost, a.used), (3, 1, 3));
+        assert_eq!((b.received, b.lost, b.used), (4, 0, 1));
+    }
+
+    #[test]
+    fn duplicates_across_paths() {
+        let mut merger = Merger::new(None);
+        // path B leads, so path A's copies all arrive second
+        for seq in 0..10 {
+            assert!(merge(&mut merger, 1, seq, 0x1234));
+        }
+        for seq in 0..10 {
+            assert!(!merge(&mut merger, 0, seq, 0x1234));
+        }
+        // a copy repeated on the same path is also suppressed
+        assert!(!merge(&mut merger, 1, 9, 0x1234));
+        assert_eq!((merger.paths[0].used, merger.paths[1].used), (0, 10));
+    }
+
+    #[test]
+    fn per_path_loss() {
+        let mut merger = Merger::new(None);
+        let mut used = 0;
+        for seq in 0..10 {
+            if !(2..=4).contains(&seq) && merge(&mut merger, 0, seq, 0x1234) {
+                used += 1;
+            }
+            if seq != 7 && merge(&mut merger, 1, seq, 0x1234) {
+                used += 1;
+            }
+        }
+        // every packet is passed on once, despite the loss on each path
+        assert_eq!(used, 10);
+        assert_eq!((merger.paths[0].lost, merger.paths[1].lost), (3, 1));
+    }
+
+    #[test]
+    fn path_outage() {
+        let mut merger = Merger::new(None);
+        for seq in 0..10 {
+            merge(&mut merger, 0, seq, 0x1234);
+        }
+        // path A is cut off for longer than MAX_DROPOUT packets, while path B carries on
+        for seq in 0..5000 {
+            merge(&mut merger, 1, seq, 0x1234);
+        }
+        assert!(merge(&mut merger, 0, 5000, 0x1234));
+        assert_eq!((merger.paths[0].lost, merger.paths[1].lost), (4990, 0));
+    }
+
+    #[test]
+    fn sender_restart() {
+        let mut merger = Merger::new(None);
+        for seq in 0..10 {
+            merge(&mut merger, 0, seq, 0x1234);
+            merge(&mut merger, 1, seq, 0x1234);
+        }
+        // both paths jump to new sequence numbers, which isn't loss on either
+        assert!(merge(&mut merger, 0, 40000, 0x1234));
+        assert!(!merge(&mut merger, 1, 40000, 0x1234));
+        assert!(merge(&mut merger, 1, 40001, 0x1234));
+        assert_eq!((merger.paths[0].lost, merger.paths[1].lost), (0, 0));
+    }
+
+    #[test]
+    fn merge_chosen_ssrc() {
+        let mut merger = Merger::new(Some(0x1234));
+        // another sender's packet would otherwise mark sequence number 0 as seen
+        assert!(!merge(&mut merger, 0, 0, 0xabcd));
+        assert!(merge(&mut merger, 1, 0, 0x1234));
+        assert!(!merge(&mut merger, 0, 0, 0x1234));
+        assert_eq!((merger.paths[0].ignored, merger.paths[1].ignored), (1, 0));
+        assert_eq!(merger.paths[0].received, 1);
+    }
+
+    #[test]
+    fn merged_late_and_duplicate() {
+        // merged paths may trail each other by more than MAX_MISORDER packets
+        let mut buf = ReorderBuffer::new(4, true);
+        let seqs: Vec<u16> = (0..=400).filter(|&seq| seq != 10).collect();
+        push_all(&mut buf, &seqs);
+        assert_eq!(counts(&buf), (1, 0, 0, 0));
+        assert!(push_all(&mut buf, &[10]).is_empty());
+        assert_eq!(counts(&buf), (1, 0, 0, 1));
+        assert!(push_all(&mut buf, &[10, 200]).is_empty());
+        assert_eq!(counts(&buf), (1, 0, 2, 1));
+    }
+
+    #[test]
+    fn merged_ssrcs() {
+        // each path's sender has an SSRC of its own
+        let mut stats = RtpStats::new(None, true);
+        let start = Instant::now();
+        for seq in 0..10 {
+            let ssrc = if seq % 2 == 0 { 0x1111 } else { 0x2222 };
+            let data = rtp_packet(seq, u32::from(seq) * 900, ssrc, PAYLOAD_TYPE_MP2T);
+            assert!(matches!(
+                stats_packet(&mut stats, &data, start, u64::from(seq) * 10),
+                Source::Same
+            ));
+        }
+        assert_eq!(stats.wanted, None);
+        assert_eq!((stats.packets, stats.ignored), (10, 0));
+        assert_eq!(stats.jitter, 0.0);
+    }
+}
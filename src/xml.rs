@@ -0,0 +1,877 @@
+//! Conversion of splice_info_section data to and from SCTE 35 XML markup, and reading of DASH MPD
+//! `EventStream` signalling
+
+use crate::cli;
+use crate::mpegts;
+use base64::Engine as _;
+use std::fmt::Write;
+use std::io;
+
+/// The namespace of SCTE 35 XML elements, as used in DASH `EventStream` signalling
+pub const NAMESPACE: &str = "http://www.scte.org/schemas/35/2016";
+
+/// Minimal XML writer producing indented output
+pub struct Writer {
+    out: String,
+    depth: usize,
+}
+impl Writer {
+    pub fn new(depth: usize) -> Writer {
+        Writer {
+            out: String::new(),
+            depth,
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attrs: &[(&str, String)]) {
+        let _ = write!(self.out, "{:width$}<{}", "", name, width = self.depth * 2);
+        for (attr, value) in attrs {
+            let _ = write!(self.out, " {}=\"{}\"", attr, escape(value));
+        }
+    }
+    /// Starts an element which will have child elements, to be finished by `close()`
+    pub fn open(&mut self, name: &str, attrs: &[(&str, String)]) {
+        self.start_tag(name, attrs);
+        self.out.push_str(">\n");
+        self.depth += 1;
+    }
+    pub fn close(&mut self, name: &str) {
+        self.depth -= 1;
+        let _ = writeln!(self.out, "{:width$}</{}>", "", name, width = self.depth * 2);
+    }
+    /// Writes an element with no content
+    pub fn empty(&mut self, name: &str, attrs: &[(&str, String)]) {
+        self.start_tag(name, attrs);
+        self.out.push_str("/>\n");
+    }
+    /// Writes a comment, which must not contain `--`, so any occurrence is broken up
+    pub fn comment(&mut self, text: &str) {
+        let _ = writeln!(
+            self.out,
+            "{:width$}<!-- {} -->",
+            "",
+            text.replace("--", "- -"),
+            width = self.depth * 2
+        );
+    }
+    /// Writes an element containing only the given text
+    pub fn text(&mut self, name: &str, attrs: &[(&str, String)], text: &str) {
+        self.start_tag(name, attrs);
+        let _ = writeln!(self.out, ">{}</{}>", escape(text), name);
+    }
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Shorthand for an attribute name and value
+fn a(name: &'static str, value: impl ToString) -> (&'static str, String) {
+    (name, value.to_string())
+}
+
+/// Writes a `scte35:SpliceInfoSection` element describing the given section
+pub fn splice_info_section(
+    w: &mut Writer,
+    header: &scte35_reader::SpliceInfoHeader<'_>,
+    command: &scte35_reader::SpliceCommand,
+    descriptors: &scte35_reader::SpliceDescriptors<'_>,
+) {
+    w.open(
+        "scte35:SpliceInfoSection",
+        &[
+            a("xmlns:scte35", NAMESPACE),
+            a("protocolVersion", header.protocol_version()),
+            a("ptsAdjustment", header.pts_adjustment()),
+            a("tier", header.tier()),
+        ],
+    );
+    splice_command(w, command);
+    for desc in descriptors.into_iter().flatten() {
+        splice_descriptor(w, &desc);
+    }
+    w.close("scte35:SpliceInfoSection");
+}
+
+fn splice_command(w: &mut Writer, command: &scte35_reader::SpliceCommand) {
+    match command {
+        scte35_reader::SpliceCommand::SpliceNull {} => w.empty("scte35:SpliceNull", &[]),
+        scte35_reader::SpliceCommand::BandwidthReservation {} => {
+            w.empty("scte35:BandwidthReservation", &[])
+        }
+        scte35_reader::SpliceCommand::TimeSignal { splice_time } => {
+            w.open("scte35:TimeSignal", &[]);
+            self::splice_time(w, splice_time);
+            w.close("scte35:TimeSignal");
+        }
+        scte35_reader::SpliceCommand::SpliceInsert {
+            splice_event_id,
+            splice_detail,
+            ..
+        } => match splice_detail {
+            scte35_reader::SpliceInsert::Cancel => w.empty(
+                "scte35:SpliceInsert",
+                &[
+                    a("spliceEventId", splice_event_id),
+                    a("spliceEventCancelIndicator", true),
+                ],
+            ),
+            scte35_reader::SpliceInsert::Insert {
+                network_indicator,
+                splice_mode,
+                duration,
+                unique_program_id,
+                avail_num,
+                avails_expected,
+            } => {
+                let immediate = match splice_mode {
+                    scte35_reader::SpliceMode::Program(t) => {
+                        matches!(t, scte35_reader::SpliceTime::Immediate)
+                    }
+                    scte35_reader::SpliceMode::Components(_) => false,
+                };
+                w.open(
+                    "scte35:SpliceInsert",
+                    &[
+                        a("spliceEventId", splice_event_id),
+                        a("spliceEventCancelIndicator", false),
+                        a(
+                            "outOfNetworkIndicator",
+                            matches!(network_indicator, scte35_reader::NetworkIndicator::Out),
+                        ),
+                        a("spliceImmediateFlag", immediate),
+                        a("uniqueProgramId", unique_program_id),
+                        a("availNum", avail_num),
+                        a("availsExpected", avails_expected),
+                    ],
+                );
+                match splice_mode {
+                    scte35_reader::SpliceMode::Program(t) => {
+                        w.open("scte35:Program", &[]);
+                        if !immediate {
+                            splice_time(w, t);
+                        }
+                        w.close("scte35:Program");
+                    }
+                    scte35_reader::SpliceMode::Components(components) => {
+                        for c in components {
+                            component_splice(w, c);
+                        }
+                    }
+                }
+                if let Some(duration) = duration {
+                    break_duration(w, duration);
+                }
+                w.close("scte35:SpliceInsert");
+            }
+        },
+    }
+}
+
+fn splice_time(w: &mut Writer, splice_time: &scte35_reader::SpliceTime) {
+    match splice_time {
+        scte35_reader::SpliceTime::Timed(Some(pts)) => {
+            w.empty("scte35:SpliceTime", &[a("ptsTime", pts)])
+        }
+        _ => w.empty("scte35:SpliceTime", &[]),
+    }
+}
+
+fn break_duration(w: &mut Writer, duration: &scte35_reader::SpliceDuration) {
+    // the fields of SpliceDuration are only exposed through its serde implementation
+    let fields = serde_json::to_value(duration).unwrap_or_default();
+    w.empty(
+        "scte35:BreakDuration",
+        &[
+            a("autoReturn", fields["return_mode"] == "Automatic"),
+            a("duration", &fields["duration"]),
+        ],
+    );
+}
+
+/// Writes a `Component` of a `splice_insert()`, whose fields `ComponentSplice` only exposes
+/// through its serde implementation
+fn component_splice(w: &mut Writer, component: &scte35_reader::ComponentSplice) {
+    let fields = serde_json::to_value(component).unwrap_or_default();
+    let tag = a("componentTag", &fields["component_tag"]);
+    match fields["splice_time"]["Timed"].as_u64() {
+        Some(pts) => {
+            w.open("scte35:Component", &[tag]);
+            w.empty("scte35:SpliceTime", &[a("ptsTime", pts)]);
+            w.close("scte35:Component");
+        }
+        // an immediate splice
+        None => w.empty("scte35:Component", &[tag]),
+    }
+}
+
+fn splice_descriptor(w: &mut Writer, desc: &scte35_reader::SpliceDescriptor) {
+    match desc {
+        scte35_reader::SpliceDescriptor::AvailDescriptor { provider_avail_id } => w.empty(
+            "scte35:AvailDescriptor",
+            &[a("providerAvailId", provider_avail_id)],
+        ),
+        scte35_reader::SpliceDescriptor::DTMFDescriptor {
+            preroll,
+            dtmf_chars,
+        } => w.empty(
+            "scte35:DTMFDescriptor",
+            &[
+                a("preroll", preroll),
+                a("chars", String::from_utf8_lossy(dtmf_chars)),
+            ],
+        ),
+        scte35_reader::SpliceDescriptor::SegmentationDescriptor {
+            segmentation_event_id,
+            descriptor_detail,
+        } => segmentation_descriptor(w, *segmentation_event_id, descriptor_detail),
+        scte35_reader::SpliceDescriptor::TimeDescriptor {
+            tai_seconds,
+            tai_nanoseconds,
+            utc_offset,
+        } => w.empty(
+            "scte35:TimeDescriptor",
+            &[
+                a("taiSeconds", tai_seconds),
+                a("taiNs", tai_nanoseconds),
+                a("utcOffset", utc_offset),
+            ],
+        ),
+        // the schema has no element for descriptors it does not define, so these are noted in a
+        // comment rather than dropped silently
+        scte35_reader::SpliceDescriptor::Reserved {
+            tag,
+            identifier,
+            private_bytes,
+        } => w.comment(&format!(
+            "splice_descriptor tag={:#04x} identifier={:?} private_bytes={}",
+            tag,
+            String::from_utf8_lossy(identifier),
+            hex::encode(private_bytes)
+        )),
+    }
+}
+
+fn segmentation_descriptor(
+    w: &mut Writer,
+    segmentation_event_id: u32,
+    detail: &scte35_reader::SegmentationDescriptor,
+) {
+    let scte35_reader::SegmentationDescriptor::Insert {
+        delivery_restrictions,
+        segmentation_mode,
+        segmentation_duration,
+        segmentation_upid,
+        segmentation_type_id,
+        segment_num,
+        segments_expected,
+        sub_segments,
+        ..
+    } = detail
+    else {
+        w.empty(
+            "scte35:SegmentationDescriptor",
+            &[
+                a("segmentationEventId", segmentation_event_id),
+                a("segmentationEventCancelIndicator", true),
+            ],
+        );
+        return;
+    };
+    let mut attrs = vec![
+        a("segmentationEventId", segmentation_event_id),
+        a("segmentationEventCancelIndicator", false),
+    ];
+    if let Some(duration) = segmentation_duration {
+        attrs.push(a("segmentationDuration", duration));
+    }
+    attrs.push(a(
+        "segmentationTypeId",
+        segmentation_type_id_value(segmentation_type_id),
+    ));
+    attrs.push(a("segmentNum", segment_num));
+    attrs.push(a("segmentsExpected", segments_expected));
+    if let Some(sub_segments) = sub_segments {
+        // SubSegments only exposes its fields through its serde implementation
+        let fields = serde_json::to_value(sub_segments).unwrap_or_default();
+        attrs.push(a("subSegmentNum", &fields["sub_segment_num"]));
+        attrs.push(a("subSegmentsExpected", &fields["sub_segments_expected"]));
+    }
+    w.open("scte35:SegmentationDescriptor", &attrs);
+    if let scte35_reader::DeliveryRestrictionFlags::DeliveryRestrictions {
+        web_delivery_allowed_flag,
+        no_regional_blackout_flag,
+        archive_allowed_flag,
+        device_restrictions,
+    } = delivery_restrictions
+    {
+        let device_restrictions = match device_restrictions {
+            scte35_reader::DeviceRestrictions::RestrictGroup0 => 0,
+            scte35_reader::DeviceRestrictions::RestrictGroup1 => 1,
+            scte35_reader::DeviceRestrictions::RestrictGroup2 => 2,
+            scte35_reader::DeviceRestrictions::None => 3,
+        };
+        w.empty(
+            "scte35:DeliveryRestrictions",
+            &[
+                a("webDeliveryAllowedFlag", web_delivery_allowed_flag),
+                a("noRegionalBlackoutFlag", no_regional_blackout_flag),
+                a("archiveAllowedFlag", archive_allowed_flag),
+                a("deviceRestrictions", device_restrictions),
+            ],
+        );
+    }
+    match segmentation_upid {
+        scte35_reader::SegmentationUpid::None => (),
+        // a MID is given as its constituent upids, in order
+        scte35_reader::SegmentationUpid::MID(upids) => {
+            for upid in upids {
+                self::segmentation_upid(w, upid);
+            }
+        }
+        upid => self::segmentation_upid(w, upid),
+    }
+    if let scte35_reader::SegmentationMode::Component { components } = segmentation_mode {
+        for c in components {
+            // SegmentationModeComponent only exposes its fields through its serde implementation
+            let fields = serde_json::to_value(c).unwrap_or_default();
+            w.empty(
+                "scte35:Component",
+                &[
+                    a("componentTag", &fields["component_tag"]),
+                    a("ptsOffset", &fields["pts_offset"]),
+                ],
+            );
+        }
+    }
+    w.close("scte35:SegmentationDescriptor");
+}
+
+/// Writes a `SegmentationUpid` element, as text for the upid types defined to hold ASCII
+/// strings, and otherwise as hex
+fn segmentation_upid(w: &mut Writer, upid: &scte35_reader::SegmentationUpid) {
+    use scte35_reader::SegmentationUpid::*;
+    let (upid_type, format, value) = match upid {
+        None => return,
+        UserDefined(v) => (0x01, "hexbinary", hex::encode(&v.0)),
+        Isci(v) => (0x02, "text", v.0.clone()),
+        AdID(v) => (0x03, "text", v.0.clone()),
+        Umid(v) => (0x04, "hexbinary", hex::encode(&v.0)),
+        // both the deprecated 8 byte and current 12 byte ISAN types are parsed into
+        // IsanDeprecated, so they are told apart by length
+        IsanDeprecated(v) if v.0.len() == 8 => (0x05, "hexbinary", hex::encode(&v.0)),
+        IsanDeprecated(v) => (0x06, "hexbinary", hex::encode(&v.0)),
+        TID(v) => (0x07, "text", v.0.clone()),
+        TI(v) => (0x08, "hexbinary", hex::encode(&v.0)),
+        ADI(v) => (0x09, "text", v.0.clone()),
+        EIDR(v) => (0x0a, "hexbinary", hex::encode(v.0)),
+        ATSC(v) => (0x0b, "hexbinary", hex::encode(&v.0)),
+        MPU(v) => (0x0c, "hexbinary", hex::encode(&v.0)),
+        MID(_) => unreachable!("MID upids are written as their constituent upids"),
+        ADS(v) => (0x0e, "hexbinary", hex::encode(&v.0)),
+        URI(v) => (0x0f, "text", v.0.to_string()),
+        Reserved(t, v) => (
+            segmentation_upid_type_value(*t),
+            "hexbinary",
+            hex::encode(v),
+        ),
+    };
+    w.text(
+        "scte35:SegmentationUpid",
+        &[
+            a("segmentationUpidType", upid_type),
+            a("segmentationUpidFormat", format),
+        ],
+        &value,
+    );
+}
+
+/// The numeric value of a `segmentation_upid_type`, which `SegmentationUpidType` does not provide
+fn segmentation_upid_type_value(upid_type: scte35_reader::SegmentationUpidType) -> u8 {
+    match upid_type {
+        scte35_reader::SegmentationUpidType::Reserved(id) => id,
+        known => (0..=u8::MAX)
+            .find(|&id| scte35_reader::SegmentationUpidType::from_type(id) == known)
+            .expect("every SegmentationUpidType has a type value"),
+    }
+}
+
+/// The numeric value of a `segmentation_type_id`, which `SegmentationTypeId` does not provide
+fn segmentation_type_id_value(type_id: &scte35_reader::SegmentationTypeId) -> u8 {
+    match *type_id {
+        scte35_reader::SegmentationTypeId::Reserved(id) => id,
+        ref known => (0..=u8::MAX)
+            .find(|&id| scte35_reader::SegmentationTypeId::from_id(id) == *known)
+            .expect("every SegmentationTypeId has an id"),
+    }
+}
+
+/// Reports the SCTE-35 signalling in a DASH MPD (from `EventStream` elements) or a standalone
+/// SCTE 35 XML `SpliceInfoSection` document
+pub fn main(cmd: &cli::XmlCmd) -> Result<(), io::Error> {
+    let text = std::fs::read_to_string(&cmd.name)?;
+    let doc = roxmltree::Document::parse(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let root = doc.root_element();
+    match root.tag_name().name() {
+        "MPD" => {
+            for stream in root.descendants().filter(|n| is(n, "EventStream")) {
+                event_stream(&doc, &stream);
+            }
+        }
+        _ => signal(&doc, &root, format!("line {}", line(&doc, &root))),
+    }
+    Ok(())
+}
+
+fn event_stream(doc: &roxmltree::Document<'_>, stream: &roxmltree::Node<'_, '_>) {
+    let scheme = stream.attribute("schemeIdUri").unwrap_or("");
+    let timescale: u64 = stream
+        .attribute("timescale")
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(1);
+    let period = stream
+        .ancestors()
+        .find(|n| is(n, "Period"))
+        .and_then(|p| p.attribute("id"));
+    for event in stream.children().filter(|n| is(n, "Event")) {
+        let mut label = format!("line {}", line(doc, &event));
+        if let Some(period) = period {
+            let _ = write!(label, ", Period {}", period);
+        }
+        let _ = write!(label, ", {}", scheme);
+        if let Some(id) = event.attribute("id") {
+            let _ = write!(label, ", Event {}", id);
+        }
+        if let Some(time) = event.attribute("presentationTime") {
+            let _ = write!(label, " presentationTime={}", time);
+            if let Ok(time) = time.parse::<u64>() {
+                let _ = write!(label, " ({:.3}s)", time as f64 / timescale as f64);
+            }
+        }
+        if let Some(duration) = event.attribute("duration") {
+            let _ = write!(label, " duration={}", duration);
+        }
+        if scheme.ends_with(":bin") && event.children().all(|n| !n.is_element()) {
+            dump(&label, decode_base64(event.text().unwrap_or("")));
+        } else {
+            signal(doc, &event, label);
+        }
+    }
+}
+
+/// Decodes any `SpliceInfoSection` or `Binary` element at or below the given node
+fn signal(doc: &roxmltree::Document<'_>, node: &roxmltree::Node<'_, '_>, label: String) {
+    let mut found = false;
+    for n in node.descendants() {
+        if is(&n, "SpliceInfoSection") {
+            dump(&label, section_from_xml(&n));
+            found = true;
+        } else if is(&n, "Binary") {
+            dump(&label, decode_base64(n.text().unwrap_or("")));
+            found = true;
+        }
+    }
+    if !found {
+        println!(
+            "[{}] no SpliceInfoSection or Binary element found at line {}",
+            label,
+            line(doc, node)
+        );
+    }
+}
+
+fn dump(label: &str, data: Result<Vec<u8>, String>) {
+    let result = data.and_then(|data| {
+        mpegts::dump_section(
+            &data,
+            mpegts::DumpSpliceInfoProcessor {
+                label: Some(label.into()),
+                ..mpegts::DumpSpliceInfoProcessor::new(None)
+            },
+        )
+    });
+    if let Err(e) = result {
+        println!("[{}] {}", label, e);
+    }
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .map_err(|e| format!("base64 decoding problem: {}", e))
+}
+
+fn line(doc: &roxmltree::Document<'_>, node: &roxmltree::Node<'_, '_>) -> u32 {
+    doc.text_pos_at(node.range().start).row
+}
+
+/// Checks the local name of an element, ignoring its namespace, since documents vary in the
+/// namespaces used for SCTE 35 markup
+fn is(node: &roxmltree::Node<'_, '_>, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn child<'a, 'input>(
+    node: &roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| is(n, name))
+}
+
+/// Reads an attribute, returning `default` if it is absent
+fn attr<T: std::str::FromStr>(
+    node: &roxmltree::Node<'_, '_>,
+    name: &str,
+    default: T,
+) -> Result<T, String> {
+    match node.attribute(name) {
+        Some(value) => value.trim().parse().map_err(|_| {
+            format!(
+                "invalid {}@{} value {:?}",
+                node.tag_name().name(),
+                name,
+                value
+            )
+        }),
+        None => Ok(default),
+    }
+}
+
+/// Reads a boolean attribute, which XML Schema allows to be given as `true`/`false` or `1`/`0`
+fn flag(node: &roxmltree::Node<'_, '_>, name: &str, default: bool) -> Result<bool, String> {
+    match node.attribute(name).map(str::trim) {
+        Some("true") | Some("1") => Ok(true),
+        Some("false") | Some("0") => Ok(false),
+        Some(value) => Err(format!(
+            "invalid {}@{} value {:?}",
+            node.tag_name().name(),
+            name,
+            value
+        )),
+        None => Ok(default),
+    }
+}
+
+/// Accumulates values of arbitrary bit-widths into bytes, most significant bit first
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    /// number of bits used in the last byte of `data` (0 meaning it is full)
+    used: u32,
+}
+impl BitWriter {
+    /// Writes `value` in `count` bits, failing if it doesn't fit rather than writing only its
+    /// low bits
+    fn bits(&mut self, count: u32, value: u64) -> Result<(), String> {
+        if count < 64 && value >> count != 0 {
+            return Err(format!("value {} does not fit in {} bits", value, count));
+        }
+        for i in (0..count).rev() {
+            self.flag((value >> i) & 1 != 0);
+        }
+        Ok(())
+    }
+    /// Writes the value of the named attribute of `node` (or `default`, if it is absent) in
+    /// `count` bits
+    fn attr(
+        &mut self,
+        count: u32,
+        node: &roxmltree::Node<'_, '_>,
+        name: &str,
+        default: u64,
+    ) -> Result<(), String> {
+        let value = attr(node, name, default)?;
+        self.bits(count, value).map_err(|_| {
+            format!(
+                "{}@{} value {} is out of range, the most allowed is {}",
+                node.tag_name().name(),
+                name,
+                value,
+                (1u64 << count) - 1
+            )
+        })
+    }
+    /// Writes the length of the named structure in `count` bits
+    fn length(&mut self, count: u32, len: usize, what: &str) -> Result<(), String> {
+        self.bits(count, len as u64).map_err(|_| {
+            format!(
+                "{} is too long, at {} bytes where the most allowed is {}",
+                what,
+                len,
+                (1u64 << count) - 1
+            )
+        })
+    }
+    fn flag(&mut self, value: bool) {
+        if self.used == 0 {
+            self.data.push(0);
+        }
+        *self.data.last_mut().unwrap() |= u8::from(value) << (7 - self.used);
+        self.used = (self.used + 1) % 8;
+    }
+    fn bytes(&mut self, data: &[u8]) {
+        for b in data {
+            for i in (0..8).rev() {
+                self.flag((b >> i) & 1 != 0);
+            }
+        }
+    }
+}
+
+/// Encodes a `SpliceInfoSection` element as a binary `splice_info_section()`, so that it can be
+/// decoded in the same way as sections from a transport stream
+pub fn section_from_xml(node: &roxmltree::Node<'_, '_>) -> Result<Vec<u8>, String> {
+    let mut command = BitWriter::default();
+    let mut command_type = None;
+    let mut descriptors = BitWriter::default();
+    for n in node.children().filter(|n| n.is_element()) {
+        let name = n.tag_name().name();
+        match name {
+            "SpliceNull" | "BandwidthReservation" | "SpliceInsert" | "TimeSignal" => {
+                if command_type.is_some() {
+                    return Err("more than one splice command given".to_string());
+                }
+                command_type = Some(match name {
+                    "SpliceNull" => 0x00,
+                    "SpliceInsert" => {
+                        splice_insert_from_xml(&mut command, &n)?;
+                        0x05
+                    }
+                    "TimeSignal" => {
+                        splice_time_from_xml(&mut command, child(&n, "SpliceTime"))?;
+                        0x06
+                    }
+                    _ => 0x07,
+                });
+            }
+            "SpliceSchedule" | "PrivateCommand" | "EncryptedPacket" => {
+                return Err(format!("{} is not supported", name));
+            }
+            _ => descriptor_from_xml(&mut descriptors, &n)?,
+        }
+    }
+    let command_type = command_type.ok_or("no splice command given")?;
+
+    let mut section = BitWriter::default();
+    section.bits(8, 0xfc)?; // table_id
+    section.flag(false); // section_syntax_indicator
+    section.flag(false); // private_indicator
+    section.bits(2, 0b11)?; // sap_type: not specified
+    let section_length = 11 + command.data.len() + 2 + descriptors.data.len() + 4;
+    section.length(12, section_length, "SpliceInfoSection")?;
+    section.attr(8, node, "protocolVersion", 0)?;
+    section.flag(false); // encrypted_packet
+    section.bits(6, 0)?; // encryption_algorithm
+    section.attr(33, node, "ptsAdjustment", 0)?;
+    section.bits(8, 0)?; // cw_index
+    section.attr(12, node, "tier", 0xfff)?;
+    section.length(12, command.data.len(), "splice command")?;
+    section.bits(8, command_type)?;
+    section.bytes(&command.data);
+    section.length(16, descriptors.data.len(), "descriptor loop")?;
+    section.bytes(&descriptors.data);
+    let crc = mpeg2ts_reader::mpegts_crc::sum32(&section.data);
+    section.bits(32, u64::from(crc))?;
+    Ok(section.data)
+}
+
+fn splice_time_from_xml(
+    w: &mut BitWriter,
+    node: Option<roxmltree::Node<'_, '_>>,
+) -> Result<(), String> {
+    match node.and_then(|n| n.attribute("ptsTime").map(|_| n)) {
+        Some(n) => {
+            w.flag(true); // time_specified_flag
+            w.bits(6, 0x3f)?;
+            w.attr(33, &n, "ptsTime", 0)?;
+        }
+        None => {
+            w.flag(false);
+            w.bits(7, 0x7f)?;
+        }
+    }
+    Ok(())
+}
+
+fn splice_insert_from_xml(w: &mut BitWriter, node: &roxmltree::Node<'_, '_>) -> Result<(), String> {
+    w.attr(32, node, "spliceEventId", 0)?;
+    let cancel = flag(node, "spliceEventCancelIndicator", false)?;
+    w.flag(cancel);
+    w.bits(7, 0x7f)?;
+    if cancel {
+        return Ok(());
+    }
+    let program = child(node, "Program");
+    let components: Vec<_> = node.children().filter(|n| is(n, "Component")).collect();
+    let break_duration = child(node, "BreakDuration");
+    let immediate = flag(node, "spliceImmediateFlag", false)?;
+    w.flag(flag(node, "outOfNetworkIndicator", false)?);
+    w.flag(program.is_some() || components.is_empty()); // program_splice_flag
+    w.flag(break_duration.is_some());
+    w.flag(immediate);
+    w.bits(4, 0xf)?;
+    if program.is_some() || components.is_empty() {
+        if !immediate {
+            splice_time_from_xml(w, program.and_then(|p| child(&p, "SpliceTime")))?;
+        }
+    } else {
+        w.bits(8, components.len() as u64)
+            .map_err(|_| "SpliceInsert has more than 255 Component elements")?;
+        for c in &components {
+            w.attr(8, c, "componentTag", 0)?;
+            if !immediate {
+                splice_time_from_xml(w, child(c, "SpliceTime"))?;
+            }
+        }
+    }
+    if let Some(d) = break_duration {
+        w.flag(flag(&d, "autoReturn", false)?);
+        w.bits(6, 0x3f)?;
+        w.attr(33, &d, "duration", 0)?;
+    }
+    w.attr(16, node, "uniqueProgramId", 0)?;
+    w.attr(8, node, "availNum", 0)?;
+    w.attr(8, node, "availsExpected", 0)?;
+    Ok(())
+}
+
+fn descriptor_from_xml(w: &mut BitWriter, node: &roxmltree::Node<'_, '_>) -> Result<(), String> {
+    let mut body = BitWriter::default();
+    let tag = match node.tag_name().name() {
+        "AvailDescriptor" => {
+            body.attr(32, node, "providerAvailId", 0)?;
+            0x00
+        }
+        "DTMFDescriptor" => {
+            let chars = node.attribute("chars").unwrap_or("");
+            // the count has only three bits
+            if chars.len() > 7 {
+                return Err(format!(
+                    "DTMFDescriptor chars {:?} has more than 7 characters",
+                    chars
+                ));
+            }
+            body.attr(8, node, "preroll", 0)?;
+            body.bits(3, chars.len() as u64)?;
+            body.bits(5, 0x1f)?;
+            body.bytes(chars.as_bytes());
+            0x01
+        }
+        "SegmentationDescriptor" => {
+            segmentation_descriptor_from_xml(&mut body, node)?;
+            0x02
+        }
+        "TimeDescriptor" => {
+            body.attr(48, node, "taiSeconds", 0)?;
+            body.attr(32, node, "taiNs", 0)?;
+            body.attr(16, node, "utcOffset", 0)?;
+            0x03
+        }
+        other => return Err(format!("unsupported element {}", other)),
+    };
+    w.bits(8, tag)?;
+    // the length includes the identifier
+    w.length(8, body.data.len() + 4, node.tag_name().name())?;
+    w.bytes(b"CUEI");
+    w.bytes(&body.data);
+    Ok(())
+}
+
+fn segmentation_descriptor_from_xml(
+    w: &mut BitWriter,
+    node: &roxmltree::Node<'_, '_>,
+) -> Result<(), String> {
+    w.attr(32, node, "segmentationEventId", 0)?;
+    let cancel = flag(node, "segmentationEventCancelIndicator", false)?;
+    w.flag(cancel);
+    w.bits(7, 0x7f)?;
+    if cancel {
+        return Ok(());
+    }
+    let components: Vec<_> = node.children().filter(|n| is(n, "Component")).collect();
+    let restrictions = child(node, "DeliveryRestrictions");
+    let duration = node.attribute("segmentationDuration");
+    w.flag(components.is_empty()); // program_segmentation_flag
+    w.flag(duration.is_some());
+    w.flag(restrictions.is_none());
+    match restrictions {
+        Some(r) => {
+            w.flag(flag(&r, "webDeliveryAllowedFlag", false)?);
+            w.flag(flag(&r, "noRegionalBlackoutFlag", false)?);
+            w.flag(flag(&r, "archiveAllowedFlag", false)?);
+            w.attr(2, &r, "deviceRestrictions", 3)?;
+        }
+        None => w.bits(5, 0x1f)?,
+    }
+    if !components.is_empty() {
+        w.bits(8, components.len() as u64)
+            .map_err(|_| "SegmentationDescriptor has more than 255 Component elements")?;
+        for c in &components {
+            w.attr(8, c, "componentTag", 0)?;
+            w.bits(7, 0x7f)?;
+            w.attr(33, c, "ptsOffset", 0)?;
+        }
+    }
+    if duration.is_some() {
+        w.attr(40, node, "segmentationDuration", 0)?;
+    }
+    let upids: Vec<_> = node
+        .children()
+        .filter(|n| is(n, "SegmentationUpid"))
+        .collect();
+    match upids[..] {
+        [] => {
+            w.bits(8, 0)?;
+            w.bits(8, 0)?;
+        }
+        [ref upid] => {
+            let data = upid_from_xml(upid)?;
+            w.attr(8, upid, "segmentationUpidType", 0)?;
+            w.length(8, data.len(), "SegmentationUpid")?;
+            w.bytes(&data);
+        }
+        _ => {
+            // several upids are combined as a MID
+            let mut mid = BitWriter::default();
+            for upid in &upids {
+                let data = upid_from_xml(upid)?;
+                mid.attr(8, upid, "segmentationUpidType", 0)?;
+                mid.length(8, data.len(), "SegmentationUpid")?;
+                mid.bytes(&data);
+            }
+            w.bits(8, 0x0d)?;
+            w.length(8, mid.data.len(), "MID of the SegmentationUpid elements")?;
+            w.bytes(&mid.data);
+        }
+    }
+    w.attr(8, node, "segmentationTypeId", 0)?;
+    w.attr(8, node, "segmentNum", 0)?;
+    w.attr(8, node, "segmentsExpected", 0)?;
+    if node.attribute("subSegmentNum").is_some() {
+        w.attr(8, node, "subSegmentNum", 0)?;
+        w.attr(8, node, "subSegmentsExpected", 0)?;
+    }
+    Ok(())
+}
+
+/// The bytes of a `SegmentationUpid` element, given in the encoding named by its
+/// `segmentationUpidFormat` attribute
+fn upid_from_xml(node: &roxmltree::Node<'_, '_>) -> Result<Vec<u8>, String> {
+    let text = node.text().unwrap_or("").trim();
+    match node
+        .attribute("segmentationUpidFormat")
+        .unwrap_or("hexbinary")
+    {
+        "hexbinary" => hex::decode(text).map_err(|e| format!("invalid hexbinary upid: {}", e)),
+        "base-64" | "base64" => decode_base64(text),
+        "text" => Ok(text.as_bytes().to_vec()),
+        other => Err(format!("unsupported segmentationUpidFormat {:?}", other)),
+    }
+}